@@ -26,6 +26,8 @@ const ASPECT_RATIO: f32 = (WINDOW_WIDTH as f32) / (WINDOW_HEIGHT as f32);
 const NEAR_PLANE_Z: f32 = 0.5;
 const FAR_PLANE_Z: f32 = 1000.0;
 const NUM_POINTS: usize = 500;
+// When true, the hull is built up one iteration at a time, advancing with the space bar
+const STEP_THROUGH: bool = false;
 
 fn rand_points_in_cube<R: rand::Rng>(num_gen: &mut R, num: usize, side: f32) -> Vec<Pt> {
   let mut rand_range = rand::distributions::Range::new(-side * 0.5, side * 0.5);
@@ -108,12 +110,15 @@ fn main() {
   // let random_points = rand_points_on_sphere(&mut rand_rng, NUM_POINTS, 1.0);
   // let random_points = same_point(NUM_POINTS);
 
-  let start = time::get_time();
-  let hull_mesh = convex_hull::get_convex_hull(random_points);
-  let duration = time::get_time() - start;
-  println!("convex hull computation took: {} seconds", duration);
+  let mut hull_builder = convex_hull::HullBuilder::new(random_points);
+  if !STEP_THROUGH {
+    let start = time::get_time();
+    while hull_builder.step().is_some() {}
+    let duration = time::get_time() - start;
+    println!("convex hull computation took: {} seconds", duration);
+  }
 
-  let hull_mesh_buffer = BufferSet::from_half_edge_mesh_flat_faces(& window, & hull_mesh);
+  let mut hull_mesh_buffer = BufferSet::from_half_edge_mesh_flat_faces(& window, hull_builder.mesh());
 
   // Vertex Shader
   let mut vert_shader_file = File::open("examples/shader/base.vs").unwrap();
@@ -184,6 +189,15 @@ fn main() {
       match event {
         glutin::Event::Closed => return,
         glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(glutin::VirtualKeyCode::Escape)) => return,
+        glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(glutin::VirtualKeyCode::Space)) => {
+          if let Some(step) = hull_builder.step() {
+            println!("face {}: eye point {:?}, {} visible faces, {} horizon edges, {} new faces, {} points remaining",
+              step.test_face, step.eye_point, step.visible_faces.len(), step.horizon.len(), step.new_faces.len(),
+              hull_builder.remaining_points().len());
+            if let Some(message) = step.error { println!("error attaching the eye point: {}", message); }
+            hull_mesh_buffer = BufferSet::from_half_edge_mesh_flat_faces(& window, hull_builder.mesh());
+          }
+        },
         _ => (),
       }
     }
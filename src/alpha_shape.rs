@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use glium::index::PrimitiveType;
+
+use defs::*;
+use delaunay::{Tetrahedralization, get_delaunay_tetrahedralization, tet_face_key};
+use mesh::{Mesh, construct_normals, get_verts_normal};
+use cgmath::prelude::*;
+
+// Alpha shapes of a point set. The Delaunay tetrahedralization is computed once, and
+// shapes for different alpha values are carved out of it. Alpha is a radius: the
+// tetrahedra whose circumspheres have a radius of at most alpha make up the shape.
+// With a large enough alpha, the shape is the convex hull.
+pub struct AlphaComplex {
+  delaunay: Tetrahedralization,
+  neighbors: Vec<[Option<usize>; 4]>,
+}
+
+impl AlphaComplex {
+  // Returns an error if the tetrahedralization fails, see get_delaunay_tetrahedralization
+  pub fn new(points_list: & Vec<Pt>) -> Result<AlphaComplex, &'static str> {
+    let delaunay = get_delaunay_tetrahedralization(points_list)?;
+    let neighbors = delaunay.neighbors();
+    Ok(AlphaComplex { delaunay: delaunay, neighbors: neighbors })
+  }
+
+  pub fn tetrahedralization(& self) -> & Tetrahedralization {
+    & self.delaunay
+  }
+
+  fn solid_tets(& self, alpha: f32) -> Vec<bool> {
+    self.delaunay.circumradii.iter().map(|& r| r <= alpha).collect()
+  }
+
+  // The triangles on the boundary of the solid tetrahedra, facing outwards
+  fn boundary_triangles(& self, solid: & [bool]) -> Vec<Tri> {
+    let mut triangles = Vec::new();
+
+    for (t_idx, tet) in self.delaunay.tets.iter().enumerate() {
+      if !solid[t_idx] { continue; }
+      for opposite in 0..4 {
+        let is_boundary = match self.neighbors[t_idx][opposite] {
+          Some(other) => !solid[other],
+          None => true,
+        };
+        if !is_boundary { continue; }
+
+        let face = tet_face_key(tet, opposite);
+        let pts = & self.delaunay.points;
+        let normal = get_verts_normal(& pts[face[0]], & pts[face[1]], & pts[face[2]]);
+        if normal.dot(pts[tet[opposite]] - pts[face[0]]) > 0.0 {
+          triangles.push([face[0], face[2], face[1]]);
+        } else {
+          triangles.push([face[0], face[1], face[2]]);
+        }
+      }
+    }
+
+    return triangles;
+  }
+
+  // Gets the boundary surface of the alpha shape, as a mesh which only contains
+  // the points that are on the boundary
+  pub fn shape(& self, alpha: f32) -> Mesh {
+    let triangles = self.boundary_triangles(& self.solid_tets(alpha));
+
+    let mut mesh = Mesh::new(PrimitiveType::TrianglesList);
+    let mut index_map: HashMap<Idx, Idx> = HashMap::new();
+
+    for tri in triangles {
+      let mut mesh_tri = [0; 3];
+      for (corner, & p_idx) in tri.iter().enumerate() {
+        mesh_tri[corner] = match index_map.get(& p_idx) {
+          Some(& m_idx) => m_idx,
+          None => {
+            mesh.add_vert(self.delaunay.points[p_idx]);
+            index_map.insert(p_idx, mesh.vert.len() - 1);
+            mesh.vert.len() - 1
+          },
+        };
+      }
+      mesh.add_tri(mesh_tri);
+    }
+
+    mesh.norm = construct_normals(& mesh.vert, & mesh.index);
+
+    return mesh;
+  }
+
+  // Finds the smallest alpha for which the shape is a single connected solid with a watertight
+  // boundary, which includes every point. The tetrahedra are added in order of increasing circumradius,
+  // and their connected components and the edges of their boundary are kept up to date as they go,
+  // so that adding each one takes constant time.
+  // Returns None if the points don't have any tetrahedra (fewer than four, or all coplanar).
+  pub fn optimal_alpha(& self) -> Option<f32> {
+    let radii = & self.delaunay.circumradii;
+    // A flat tetrahedron has no finite circumsphere, and can't be part of the shape at any alpha
+    let mut order: Vec<usize> = (0..radii.len()).filter(|& t_idx| radii[t_idx].is_finite()).collect();
+    order.sort_by(|& a, & b| radii[a].partial_cmp(& radii[b]).unwrap());
+
+    let mut parents: Vec<usize> = (0..radii.len()).collect();
+    let mut solid = vec![false; radii.len()];
+    let mut components = 0;
+
+    // Duplicate points aren't part of any tetrahedron, so they don't need to be covered
+    let mut covered = vec![true; self.delaunay.points.len()];
+    for tet in self.delaunay.tets.iter() {
+      for & v in tet.iter() { covered[v] = false; }
+    }
+    let mut uncovered_count = covered.iter().filter(|& & c| !c).count();
+
+    // How many boundary triangles each edge is on, and how many edges aren't on exactly two of them
+    let mut edge_counts: HashMap<(Idx, Idx), usize> = HashMap::new();
+    let mut unmatched_edges = 0;
+    let mut boundary_faces = 0;
+
+    let mut idx = 0;
+    while idx < order.len() {
+      let alpha = radii[order[idx]];
+      // Add every tetrahedron with this circumradius before checking
+      while idx < order.len() && radii[order[idx]] <= alpha {
+        let t_idx = order[idx];
+        solid[t_idx] = true;
+        components += 1;
+        for & v in self.delaunay.tets[t_idx].iter() {
+          if !covered[v] {
+            covered[v] = true;
+            uncovered_count -= 1;
+          }
+        }
+        for neighbor in self.neighbors[t_idx].iter().filter_map(|n| * n) {
+          if solid[neighbor] && union_sets(&mut parents, t_idx, neighbor) {
+            components -= 1;
+          }
+        }
+
+        // Each face either joins the boundary, or was on it and is now inside the solid
+        for opposite in 0..4 {
+          let joins = self.neighbors[t_idx][opposite].map_or(true, |other| !solid[other]);
+          if joins { boundary_faces += 1; } else { boundary_faces -= 1; }
+          let face = tet_face_key(& self.delaunay.tets[t_idx], opposite);
+          for & (a, b) in [(face[0], face[1]), (face[1], face[2]), (face[0], face[2])].iter() {
+            let count = edge_counts.entry((a, b)).or_insert(0);
+            if * count != 0 && * count != 2 { unmatched_edges -= 1; }
+            if joins { * count += 1; } else { * count -= 1; }
+            if * count != 0 && * count != 2 { unmatched_edges += 1; }
+          }
+        }
+        idx += 1;
+      }
+
+      if components == 1 && uncovered_count == 0 && boundary_faces > 0 && unmatched_edges == 0 {
+        return Some(alpha);
+      }
+    }
+
+    return None;
+  }
+}
+
+// Convenience function for computing a single alpha shape
+pub fn get_alpha_shape(points_list: & Vec<Pt>, alpha: f32) -> Result<Mesh, &'static str> {
+  Ok(AlphaComplex::new(points_list)?.shape(alpha))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  fn mesh_volume(mesh: & Mesh) -> f32 {
+    mesh.index.iter().map(|tri| {
+      let (a, b, c) = (mesh.vert[tri[0]].to_vec(), mesh.vert[tri[1]].to_vec(), mesh.vert[tri[2]].to_vec());
+      a.dot(b.cross(c)) / 6.0
+    }).sum()
+  }
+
+  // Checks the conditions optimal_alpha looks for from scratch: one connected solid, covering
+  // every point, with every boundary edge on exactly two triangles
+  fn is_solid_shape(complex: & AlphaComplex, alpha: f32) -> bool {
+    let delaunay = complex.tetrahedralization();
+    let solid = complex.solid_tets(alpha);
+    let mut parents: Vec<Idx> = (0..solid.len()).collect();
+    let mut covered: HashSet<Idx> = HashSet::new();
+    for t_idx in (0..solid.len()).filter(|& t_idx| solid[t_idx]) {
+      covered.extend(delaunay.tets[t_idx].iter().cloned());
+      for neighbor in complex.neighbors[t_idx].iter().filter_map(|n| * n) {
+        if solid[neighbor] { union_sets(&mut parents, t_idx, neighbor); }
+      }
+    }
+    let roots: HashSet<Idx> = (0..solid.len()).filter(|& t_idx| solid[t_idx]).map(|t_idx| find_root(&mut parents, t_idx)).collect();
+    let used: HashSet<Idx> = delaunay.tets.iter().flat_map(|tet| tet.iter().cloned()).collect();
+
+    let mut edge_counts: HashMap<(Idx, Idx), usize> = HashMap::new();
+    for tri in complex.boundary_triangles(& solid) {
+      for i in 0..3 {
+        let (a, b) = (tri[i], tri[(i + 1) % 3]);
+        * edge_counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+      }
+    }
+    roots.len() == 1 && covered == used && !edge_counts.is_empty() && edge_counts.values().all(|& count| count == 2)
+  }
+
+  #[test]
+  fn large_alpha_gives_the_hull() {
+    let points = ball_points(200, 10);
+    let complex = AlphaComplex::new(& points).unwrap();
+    let shape = complex.shape(f32::MAX);
+    // The triangles face outwards, so the volume they enclose is positive
+    assert!((mesh_volume(& shape) - get_convex_polyhedron(points).volume()).abs() < 1e-4);
+    assert_eq!(shape.norm.len(), shape.vert.len());
+    assert!(complex.shape(0.0).index.is_empty());
+  }
+
+  #[test]
+  fn optimal_alpha_of_a_grid() {
+    // Every cube of the grid has the same circumsphere, so the whole cube appears at once
+    let complex = AlphaComplex::new(& grid_points(3)).unwrap();
+    let alpha = complex.optimal_alpha().unwrap();
+    assert!((alpha - 3.0_f32.sqrt() / 2.0).abs() < 1e-4);
+    assert!((mesh_volume(& complex.shape(alpha)) - 8.0).abs() < 1e-4);
+  }
+
+  #[test]
+  fn optimal_alpha_is_the_smallest_solid_shape() {
+    for seed in 0..5 {
+      let complex = AlphaComplex::new(& ball_points(150, 20 + seed)).unwrap();
+      let alpha = complex.optimal_alpha().unwrap();
+      assert!(is_solid_shape(& complex, alpha));
+      let smaller = complex.tetrahedralization().circumradii.iter().cloned().filter(|& r| r < alpha).fold(0.0, f32::max);
+      assert!(!is_solid_shape(& complex, smaller));
+    }
+  }
+}
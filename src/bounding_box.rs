@@ -0,0 +1,223 @@
+use std::f32;
+
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::ConvexPolyhedron;
+
+// Sweeps of the Jacobi eigenvalue method, which converges well within this for 3x3 matrices
+const JACOBI_SWEEPS: usize = 16;
+
+// A box which can be rotated arbitrarily. The axes are the columns of the rotation,
+// and the box extends half_extents along each of them on both sides of the center.
+#[derive(Copy, Clone, Debug)]
+pub struct OrientedBox {
+  pub center: Pt,
+  pub rotation: Quat,
+  pub axes: [Vec3; 3],
+  pub half_extents: Vec3,
+}
+
+impl OrientedBox {
+  // Fits a box with the given right-handed, unit axes tightly around the points
+  fn fit(axes: [Vec3; 3], points: & [Pt]) -> OrientedBox {
+    let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+    for pt in points.iter() {
+      for axis in 0..3 {
+        let dist = axes[axis].dot(pt.to_vec());
+        min[axis] = min[axis].min(dist);
+        max[axis] = max[axis].max(dist);
+      }
+    }
+
+    let middle = (min + max) / 2.0;
+    OrientedBox {
+      center: Pt::from_vec(axes[0] * middle.x + axes[1] * middle.y + axes[2] * middle.z),
+      rotation: Quat::from(Mat3::from_cols(axes[0], axes[1], axes[2])).normalize(),
+      axes: axes,
+      half_extents: (max - min) / 2.0,
+    }
+  }
+
+  pub fn volume(& self) -> f32 {
+    8.0 * self.half_extents.x * self.half_extents.y * self.half_extents.z
+  }
+
+  pub fn corners(& self) -> [Pt; 8] {
+    let mut corners = [self.center; 8];
+    for (idx, corner) in corners.iter_mut().enumerate() {
+      for axis in 0..3 {
+        let sign = if idx & (1 << axis) == 0 { -1.0 } else { 1.0 };
+        * corner += self.axes[axis] * (sign * self.half_extents[axis]);
+      }
+    }
+    return corners;
+  }
+}
+
+// Two unit vectors which make a right-handed basis with the given unit normal
+pub fn perpendicular_basis(normal: Vec3) -> (Vec3, Vec3) {
+  let helper = if normal.x.abs() < 0.6 { Vec3::unit_x() } else { Vec3::unit_y() };
+  let first = normal.cross(helper).normalize();
+  (first, normal.cross(first))
+}
+
+// The 2D convex hull of some points, counterclockwise, using Andrew's monotone chain
+fn convex_hull_2d(mut points: Vec<[f32; 2]>) -> Vec<[f32; 2]> {
+  use std::cmp::Ordering::Equal;
+  points.sort_by(|a, b| a[0].partial_cmp(& b[0]).unwrap_or(Equal).then(a[1].partial_cmp(& b[1]).unwrap_or(Equal)));
+  if points.len() < 3 { return points; }
+
+  let turn = |o: [f32; 2], a: [f32; 2], b: [f32; 2]| (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0]);
+  let mut hull: Vec<[f32; 2]> = Vec::with_capacity(points.len() + 1);
+  for pass in 0..2 {
+    let start = hull.len();
+    for idx in 0..points.len() {
+      let pt = if pass == 0 { points[idx] } else { points[points.len() - 1 - idx] };
+      while hull.len() >= start + 2 && turn(hull[hull.len() - 2], hull[hull.len() - 1], pt) <= 0.0 {
+        hull.pop();
+      }
+      hull.push(pt);
+    }
+    // The last point of each chain is the first of the other
+    hull.pop();
+  }
+  return hull;
+}
+
+// The smallest box with one of its axes along the given one. The box's other two axes are found by
+// projecting the points onto the plane perpendicular to the axis, and trying each side of the
+// outline of the projection, since the smallest rectangle around a convex polygon is flush with a side
+fn best_box_around_axis(axis: Vec3, points: & [Pt]) -> OrientedBox {
+  let (first, second) = perpendicular_basis(axis);
+  let outline = convex_hull_2d(points.iter().map(|pt| [first.dot(pt.to_vec()), second.dot(pt.to_vec())]).collect());
+
+  let mut best_area = f32::MAX;
+  let mut best_side = [1.0, 0.0];
+  for (idx, & current) in outline.iter().enumerate() {
+    let next = outline[(idx + 1) % outline.len()];
+    let length = ((next[0] - current[0]).powi(2) + (next[1] - current[1]).powi(2)).sqrt();
+    if length == 0.0 { continue; }
+    let side = [(next[0] - current[0]) / length, (next[1] - current[1]) / length];
+
+    let (mut min_u, mut max_u, mut min_v, mut max_v) = (f32::MAX, f32::MIN, f32::MAX, f32::MIN);
+    for pt in outline.iter() {
+      let u = pt[0] * side[0] + pt[1] * side[1];
+      let v = pt[1] * side[0] - pt[0] * side[1];
+      min_u = min_u.min(u);
+      max_u = max_u.max(u);
+      min_v = min_v.min(v);
+      max_v = max_v.max(v);
+    }
+    let area = (max_u - min_u) * (max_v - min_v);
+    if area < best_area {
+      best_area = area;
+      best_side = side;
+    }
+  }
+
+  let box_x = first * best_side[0] + second * best_side[1];
+  let box_y = second * best_side[0] - first * best_side[1];
+  OrientedBox::fit([box_x, box_y, axis], points)
+}
+
+// The eigenvectors of a symmetric matrix, as the columns of a rotation, using the Jacobi method
+fn symmetric_eigenvectors(matrix: Mat3) -> Mat3 {
+  let mut a = matrix;
+  let mut vectors = Mat3::identity();
+  for _ in 0..JACOBI_SWEEPS {
+    for & (p, q) in [(0, 1), (0, 2), (1, 2)].iter() {
+      if a[q][p].abs() < 1e-12 { continue; }
+      // Rotate in the pq plane to zero out the off-diagonal element
+      let theta = (a[q][q] - a[p][p]) / (2.0 * a[q][p]);
+      let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+      let c = 1.0 / (t * t + 1.0).sqrt();
+      let s = t * c;
+      let mut rotation = Mat3::identity();
+      rotation[p][p] = c;
+      rotation[q][q] = c;
+      rotation[q][p] = s;
+      rotation[p][q] = -s;
+      a = rotation.transpose() * a * rotation;
+      vectors = vectors * rotation;
+    }
+  }
+  return vectors;
+}
+
+impl ConvexPolyhedron {
+  // An approximation of the smallest box around the hull. Each face normal and edge direction of the
+  // hull is tried as one of the box's axes, with the best box around that axis (which is exact for it),
+  // and the smallest of those is kept. O'Rourke showed that the smallest box is flush with a face or
+  // has hull edges on two of its faces, and this covers the first case but not all of the second.
+  // The direction of the diameter is tried as well, which bounds how far off the result can be: along it
+  // the box is the diameter long, and across it the best rectangle is at most twice the area of the hull's
+  // outline. The hull holds the double cone from the diameter over that outline, a third of the diameter
+  // times the area, so the box is at most 6 times the hull's volume, and so 6 times the smallest box.
+  // The result is never bigger than the PCA box either. Returns None if the hull is empty.
+  pub fn oriented_bounding_box(& self) -> Option<OrientedBox> {
+    let mut best = self.pca_bounding_box()?;
+
+    let face_axes = self.planes.iter().map(|plane| plane.normal);
+    let edge_axes = self.edges.iter().map(|edge| (self.vertices[edge[1]] - self.vertices[edge[0]]).normalize());
+    let diameter_axis = self.diameter().into_iter()
+      .filter(|diameter| diameter.length > 0.0)
+      .map(|diameter| (self.vertices[diameter.vertices[1]] - self.vertices[diameter.vertices[0]]).normalize());
+    for axis in face_axes.chain(edge_axes).chain(diameter_axis) {
+      let candidate = best_box_around_axis(axis, & self.vertices);
+      if candidate.volume() < best.volume() { best = candidate; }
+    }
+
+    Some(best)
+  }
+
+  // A quick box around the hull, along the principal axes of the solid hull's mass distribution.
+  // Returns None if the hull is empty.
+  pub fn pca_bounding_box(& self) -> Option<OrientedBox> {
+    if self.is_empty() { return None; }
+    // The inertia tensor has the same eigenvectors as the covariance matrix
+    let vectors = symmetric_eigenvectors(self.inertia_tensor(1.0));
+    let axes = [vectors.x.normalize(), vectors.y.normalize(), vectors.x.cross(vectors.y).normalize()];
+    Some(OrientedBox::fit(axes, & self.vertices))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::Rad;
+
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  #[test]
+  fn turned_box_is_found() {
+    let turn = Quat::from_axis_angle(Vec3::new(2.0, -1.0, 0.5).normalize(), Rad(0.8));
+    let corners: Vec<Pt> = box_corners(Pt::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, 0.5)).into_iter()
+      .map(|pt| Pt::from_vec(turn.rotate_vector(pt.to_vec()))).collect();
+    let found = get_convex_polyhedron(corners).oriented_bounding_box().unwrap();
+    assert!((found.volume() - 8.0).abs() < 1e-3);
+  }
+
+  #[test]
+  fn within_six_times_the_hull() {
+    for seed in 0..10 {
+      // Squashed and turned, so the box isn't lined up with the coordinate axes
+      let turn = Quat::from_axis_angle(Vec3::new(1.0, seed as f32, 2.0).normalize(), Rad(0.3 * seed as f32));
+      let points: Vec<Pt> = ball_points(200, seed).into_iter()
+        .map(|pt| Pt::from_vec(turn.rotate_vector(Vec3::new(pt.x * 3.0, pt.y, pt.z * 0.4)))).collect();
+      let hull = get_convex_polyhedron(points);
+      let found = hull.oriented_bounding_box().unwrap();
+      assert!(found.volume() <= 6.0 * hull.volume());
+      assert!(found.volume() <= hull.pca_bounding_box().unwrap().volume() + 1e-4);
+      // Every vertex is inside of the box
+      for & v in hull.vertices.iter() {
+        for axis in 0..3 {
+          assert!(found.axes[axis].dot(v - found.center).abs() <= found.half_extents[axis] + 1e-4);
+        }
+      }
+    }
+  }
+}
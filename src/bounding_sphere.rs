@@ -0,0 +1,176 @@
+use cgmath::prelude::*;
+use cgmath::{Point3, Vector3};
+
+use defs::*;
+use polyhedron::ConvexPolyhedron;
+use convex_hull::{get_convex_hull, HullPoint};
+
+// Points are treated as inside a sphere when they're within this fraction of its radius outside of it.
+// Without some slack, points which are all on one sphere (or very nearly) would keep knocking each
+// other out of the spheres made from the others, because of rounding
+const ENCLOSING_EPSILON: f64 = 1e-6;
+
+// The smallest sphere around a set of points, along with the points on its surface which define it
+// (between one and four of them)
+#[derive(Clone, Debug)]
+pub struct BoundingSphere {
+  pub center: Pt,
+  pub radius: f32,
+  pub support: Vec<Pt>,
+}
+
+// The sums get big enough to lose precision in f32, so the work is done in f64
+type DPt = Point3<f64>;
+type DVec = Vector3<f64>;
+
+#[derive(Copy, Clone)]
+struct Ball {
+  center: DPt,
+  radius2: f64,
+}
+
+fn widen(pt: Pt) -> DVec {
+  DVec::new(pt.x as f64, pt.y as f64, pt.z as f64)
+}
+
+fn narrow(vec: DVec) -> Pt {
+  Pt::new(vec.x as f32, vec.y as f32, vec.z as f32)
+}
+
+impl Ball {
+  fn contains(& self, pt: DPt) -> bool {
+    (pt - self.center).magnitude2() <= self.radius2 * (1.0 + ENCLOSING_EPSILON).powi(2)
+  }
+}
+
+// The smallest ball with all of the points on its surface.
+// Degenerate sets (collinear or coplanar points) fall back to the ball of a subset which contains the rest.
+fn circumscribed_ball(boundary: & [DPt]) -> Ball {
+  match boundary.len() {
+    0 => Ball { center: DPt::origin(), radius2: -1.0 },
+    1 => Ball { center: boundary[0], radius2: 0.0 },
+    2 => {
+      let center = boundary[0].midpoint(boundary[1]);
+      Ball { center: center, radius2: (boundary[0] - center).magnitude2() }
+    },
+    3 => {
+      let (u, v) = (boundary[1] - boundary[0], boundary[2] - boundary[0]);
+      let w = u.cross(v);
+      let denominator = 2.0 * w.magnitude2();
+      if denominator <= 1e-12 * u.magnitude2() * v.magnitude2() { return ball_of_subsets(boundary); }
+      let offset = (v.cross(w) * u.magnitude2() + w.cross(u) * v.magnitude2()) / denominator;
+      Ball { center: boundary[0] + offset, radius2: offset.magnitude2() }
+    },
+    _ => {
+      let (u, v, w) = (boundary[1] - boundary[0], boundary[2] - boundary[0], boundary[3] - boundary[0]);
+      let denominator = 2.0 * u.dot(v.cross(w));
+      if denominator.abs() <= 1e-12 * u.magnitude() * v.magnitude() * w.magnitude() { return ball_of_subsets(boundary); }
+      let offset = (v.cross(w) * u.magnitude2() + w.cross(u) * v.magnitude2() + u.cross(v) * w.magnitude2()) / denominator;
+      Ball { center: boundary[0] + offset, radius2: offset.magnitude2() }
+    },
+  }
+}
+
+// The smallest of the balls through all but one of the points which contains the left out point
+fn ball_of_subsets(boundary: & [DPt]) -> Ball {
+  let mut best: Option<Ball> = None;
+  for skip in 0..boundary.len() {
+    let subset: Vec<DPt> = boundary.iter().enumerate().filter(|& (idx, _)| idx != skip).map(|(_, & pt)| pt).collect();
+    let ball = circumscribed_ball(& subset);
+    if !ball.contains(boundary[skip]) { continue; }
+    if best.map_or(true, |b| ball.radius2 < b.radius2) { best = Some(ball); }
+  }
+  best.unwrap_or_else(|| circumscribed_ball(& boundary[0..2]))
+}
+
+// Welzl's algorithm, in Gärtner's move-to-front form: finds the smallest ball around the first `end` points
+// which has the boundary points on its surface. Points found outside of the ball are moved to the front
+// of the list, so that later calls check them first. The recursion is at most four levels deep.
+fn move_to_front_ball(points: &mut Vec<DPt>, end: usize, boundary: &mut Vec<DPt>) -> (Ball, Vec<DPt>) {
+  let mut ball = circumscribed_ball(boundary);
+  let mut support = boundary.clone();
+  if boundary.len() == 4 { return (ball, support); }
+
+  for idx in 0..end {
+    let pt = points[idx];
+    if ball.contains(pt) { continue; }
+
+    boundary.push(pt);
+    let (new_ball, new_support) = move_to_front_ball(points, idx, boundary);
+    boundary.pop();
+    ball = new_ball;
+    support = new_support;
+
+    points.remove(idx);
+    points.insert(0, pt);
+  }
+
+  return (ball, support);
+}
+
+// Finds the smallest sphere containing all of the points, using Welzl's algorithm.
+// Returns None if there are no points.
+pub fn get_minimum_enclosing_sphere(points: & [Pt]) -> Option<BoundingSphere> {
+  if points.is_empty() { return None; }
+
+  // Work relative to one of the points, to keep the numbers small
+  let origin = widen(points[0]);
+  let mut list: Vec<DPt> = points.iter().map(|& pt| DPt::from_vec(widen(pt) - origin)).collect();
+  let end = list.len();
+  let (ball, support) = move_to_front_ball(&mut list, end, &mut Vec::with_capacity(4));
+
+  Some(BoundingSphere {
+    center: narrow(origin + ball.center.to_vec()),
+    radius: ball.radius2.max(0.0).sqrt() as f32,
+    support: support.into_iter().map(|pt| narrow(origin + pt.to_vec())).collect(),
+  })
+}
+
+// Finds the smallest sphere around the points, by running Welzl's algorithm on just the vertices of
+// their convex hull, which are usually far fewer than the points. Returns None if there are no points.
+pub fn get_bounding_sphere<P: HullPoint>(points_list: Vec<P>) -> Option<BoundingSphere> {
+  let positions: Vec<Pt> = points_list.iter().map(|p| p.position()).collect();
+  let hull = ConvexPolyhedron::from_half_edge_mesh(& get_convex_hull(positions.clone()));
+  // Fewer than four points, or points which are all in a plane, don't make a proper hull
+  if hull.volume() <= 0.0 { return get_minimum_enclosing_sphere(& positions); }
+  get_minimum_enclosing_sphere(& hull.vertices)
+}
+
+impl ConvexPolyhedron {
+  // The smallest sphere around the hull. Returns None if the hull is empty.
+  pub fn bounding_sphere(& self) -> Option<BoundingSphere> {
+    if self.is_empty() { return None; }
+    get_minimum_enclosing_sphere(& self.vertices)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use defs::*;
+  use test_support::*;
+  use super::*;
+
+  #[test]
+  fn box_sphere_is_through_its_corners() {
+    let corners = box_corners(Pt::new(3.0, -1.0, 2.0), Vec3::new(1.0, 2.0, 0.5));
+    let sphere = get_minimum_enclosing_sphere(& corners).unwrap();
+    assert!((sphere.radius - 5.25_f32.sqrt()).abs() < 1e-4);
+    assert!((sphere.center - Pt::new(3.0, -1.0, 2.0)).magnitude() < 1e-4);
+  }
+
+  #[test]
+  fn scattered_points_are_enclosed() {
+    for seed in 0..10 {
+      let points = ball_points(500, seed);
+      let sphere = get_minimum_enclosing_sphere(& points).unwrap();
+      assert!(sphere.radius <= 1.0 + 1e-4);
+      assert!(sphere.support.len() >= 2 && sphere.support.len() <= 4);
+      for & pt in points.iter() { assert!((pt - sphere.center).magnitude() <= sphere.radius * (1.0 + 1e-4)); }
+      // The points which define the sphere are on its surface
+      for & pt in sphere.support.iter() { assert!(((pt - sphere.center).magnitude() - sphere.radius).abs() < 1e-4); }
+
+      let from_hull = get_bounding_sphere(points).unwrap();
+      assert!((from_hull.radius - sphere.radius).abs() < 1e-4);
+    }
+  }
+}
@@ -0,0 +1,295 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::{ConvexPolyhedron, Plane};
+use convex_hull::chain_edges;
+use bounding_box::perpendicular_basis;
+
+// Vertices within this distance of the cutting plane, relative to the size of the hull,
+// are treated as being on it, so that nearly flat slivers aren't cut off
+const CLIP_EPSILON: f32 = 1e-6;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Side {
+  Behind,
+  On,
+  Front,
+}
+
+// The polygon where a plane cuts through a hull, counterclockwise around the plane's normal.
+// The points are in the plane's own coordinates: distances along the two axes from the origin,
+// which is the point of the plane closest to the world origin.
+#[derive(Clone, Debug)]
+pub struct CrossSection {
+  pub origin: Pt,
+  pub axes: [Vec3; 2],
+  pub points: Vec<[f32; 2]>,
+}
+
+impl CrossSection {
+  pub fn to_world(& self) -> Vec<Pt> {
+    self.points.iter().map(|pt| self.origin + self.axes[0] * pt[0] + self.axes[1] * pt[1]).collect()
+  }
+
+  pub fn area(& self) -> f32 {
+    let mut twice_area = 0.0;
+    for (idx, current) in self.points.iter().enumerate() {
+      let next = self.points[(idx + 1) % self.points.len()];
+      twice_area += current[0] * next[1] - next[0] * current[1];
+    }
+    return twice_area / 2.0;
+  }
+}
+
+// The loop of vertices which closes off the open side of a set of faces,
+// found by following the edges which only one of the faces has, backwards
+fn cap_loop(faces: & [Vec<Idx>]) -> Vec<Idx> {
+  let mut directed: HashSet<(Idx, Idx)> = HashSet::new();
+  for face in faces.iter() {
+    for (idx, & from) in face.iter().enumerate() {
+      directed.insert((from, face[(idx + 1) % face.len()]));
+    }
+  }
+
+  let open = directed.iter()
+    .filter(|& & (from, to)| !directed.contains(& (to, from)))
+    .map(|& (from, to)| (to as u32, from as u32))
+    .collect();
+  chain_edges(open).into_iter().map(|(from, _)| from as Idx).collect()
+}
+
+// Makes one half of a split into a polyhedron, keeping only the vertices it uses.
+// Each face has its plane and id given along with it.
+fn build_half(vertices: & [Pt], vertex_ids: & [u32], faces: Vec<Vec<Idx>>, planes: Vec<Plane>, face_ids: Vec<u32>) -> ConvexPolyhedron {
+  let mut used = vec![false; vertices.len()];
+  for face in faces.iter() {
+    for & v in face.iter() { used[v] = true; }
+  }
+
+  let mut new_index: HashMap<Idx, Idx> = HashMap::new();
+  let mut kept_vertices = Vec::new();
+  let mut kept_ids = Vec::new();
+  for v in (0..vertices.len()).filter(|& v| used[v]) {
+    new_index.insert(v, kept_vertices.len());
+    kept_vertices.push(vertices[v]);
+    kept_ids.push(vertex_ids[v]);
+  }
+
+  let faces = faces.into_iter().map(|face| face.into_iter().map(|v| new_index[& v]).collect()).collect();
+  let mut polyhedron = ConvexPolyhedron::from_faces(kept_vertices, faces);
+  polyhedron.planes = planes;
+  polyhedron.vertex_ids = kept_ids;
+  polyhedron.face_ids = face_ids;
+  return polyhedron;
+}
+
+impl ConvexPolyhedron {
+  // Cuts the hull in two with a plane, returning the part behind the plane, the part in front of it,
+  // and the cross-section where they meet. Both parts are closed off with the cross-section as a new face.
+  // Faces and vertices which survive the cut keep their ids (faces which are cut keep the id of the face
+  // they're part of). The new vertices get ids after the largest existing one, and the new face gets the
+  // id after the largest face id, in both parts. If the plane doesn't cut through the hull, the whole hull
+  // is on one side, and there's no cross-section.
+  pub fn split_by_plane(& self, plane: & Plane) -> (Option<ConvexPolyhedron>, Option<ConvexPolyhedron>, Option<CrossSection>) {
+    let cap_id = self.face_ids.iter().cloned().max().map_or(0, |id| id + 1);
+    self.split_by_plane_with_id(plane, cap_id)
+  }
+
+  // Splits the hull by the plane, giving the new face in both parts the given id
+  pub fn split_by_plane_with_id(& self, plane: & Plane, cap_id: u32) -> (Option<ConvexPolyhedron>, Option<ConvexPolyhedron>, Option<CrossSection>) {
+    if self.is_empty() { return (None, None, None); }
+
+    let tolerance = scaled_tolerance(CLIP_EPSILON, self.vertices.iter().cloned());
+    let distances: Vec<f32> = self.vertices.iter().map(|& pt| plane.signed_distance(pt)).collect();
+    let sides: Vec<Side> = distances.iter()
+      .map(|& d| if d > tolerance { Side::Front } else if d < -tolerance { Side::Behind } else { Side::On })
+      .collect();
+
+    if !sides.contains(& Side::Front) { return (Some(self.clone()), None, None); }
+    if !sides.contains(& Side::Behind) { return (None, Some(self.clone()), None); }
+
+    // Add a vertex where each edge crosses the plane, which both parts share
+    let mut vertices = self.vertices.clone();
+    let mut vertex_ids = self.vertex_ids.clone();
+    let mut next_vertex_id = self.vertex_ids.iter().cloned().max().map_or(0, |id| id + 1);
+    let mut crossings: Vec<Option<Idx>> = vec![None; self.edges.len()];
+    for (e_idx, edge) in self.edges.iter().enumerate() {
+      let (from, to) = (edge[0], edge[1]);
+      if sides[from] == Side::On || sides[to] == Side::On || sides[from] == sides[to] { continue; }
+      let t = distances[from] / (distances[from] - distances[to]);
+      vertices.push(self.vertices[from] + (self.vertices[to] - self.vertices[from]) * t);
+      vertex_ids.push(next_vertex_id);
+      next_vertex_id += 1;
+      crossings[e_idx] = Some(vertices.len() - 1);
+    }
+
+    // Split each face, keeping whichever parts of it are more than a sliver.
+    // Vertices on the plane go in both parts.
+    let mut behind_faces: Vec<Vec<Idx>> = Vec::new();
+    let mut behind_sources: Vec<Idx> = Vec::new();
+    let mut front_faces: Vec<Vec<Idx>> = Vec::new();
+    let mut front_sources: Vec<Idx> = Vec::new();
+    for (f_idx, face) in self.faces.iter().enumerate() {
+      let mut behind = Vec::with_capacity(face.len() + 1);
+      let mut front = Vec::with_capacity(face.len() + 1);
+      for (idx, & v) in face.iter().enumerate() {
+        if sides[v] != Side::Front { behind.push(v); }
+        if sides[v] != Side::Behind { front.push(v); }
+        if let Some(crossing) = crossings[self.face_edges[f_idx][idx]] {
+          behind.push(crossing);
+          front.push(crossing);
+        }
+      }
+      if behind.len() >= 3 {
+        behind_faces.push(behind);
+        behind_sources.push(f_idx);
+      }
+      if front.len() >= 3 {
+        front_faces.push(front);
+        front_sources.push(f_idx);
+      }
+    }
+
+    let behind_cap = cap_loop(& behind_faces);
+    let front_cap = cap_loop(& front_faces);
+    let section_points: Vec<Pt> = behind_cap.iter().map(|& v| vertices[v]).collect();
+
+    let make_half = |mut faces: Vec<Vec<Idx>>, sources: Vec<Idx>, cap: Vec<Idx>, cap_plane: Plane| {
+      let mut planes: Vec<Plane> = sources.iter().map(|& f| self.planes[f]).collect();
+      let mut face_ids: Vec<u32> = sources.iter().map(|& f| self.face_ids[f]).collect();
+      faces.push(cap);
+      planes.push(cap_plane);
+      face_ids.push(cap_id);
+      build_half(& vertices, & vertex_ids, faces, planes, face_ids)
+    };
+    let behind = make_half(behind_faces, behind_sources, behind_cap, * plane);
+    let front = make_half(front_faces, front_sources, front_cap, plane.flipped());
+
+    let origin = Pt::from_vec(plane.normal * plane.offset);
+    let (first, second) = perpendicular_basis(plane.normal);
+    let section = CrossSection {
+      origin: origin,
+      axes: [first, second],
+      points: section_points.iter().map(|& pt| [first.dot(pt - origin), second.dot(pt - origin)]).collect(),
+    };
+
+    (Some(behind), Some(front), Some(section))
+  }
+}
+
+// Which of the two hulls a face of their intersection came from, and the index of the face in that hull
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FaceSource {
+  A(Idx),
+  B(Idx),
+}
+
+// The region where two hulls overlap, with the source of each of its faces
+#[derive(Clone, Debug)]
+pub struct Intersection {
+  pub hull: ConvexPolyhedron,
+  pub face_sources: Vec<FaceSource>,
+}
+
+// Intersects the halfspaces behind the face planes of both hulls, by clipping the first hull by each of the
+// second hull's planes in turn. The faces keep their ids from the hull they came from, and the vertices are
+// numbered in order. The intersection is empty if the hulls don't overlap, or only touch.
+pub fn intersect_hulls(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> Intersection {
+  let empty = Intersection { hull: ConvexPolyhedron::empty(), face_sources: Vec::new() };
+  if a.is_empty() || b.is_empty() { return empty; }
+
+  // While clipping, the face ids say where each face came from: A's faces are numbered first, then B's
+  let mut clipped = a.clone();
+  clipped.face_ids = (0..a.faces.len() as u32).collect();
+  for (face, plane) in b.planes.iter().enumerate() {
+    clipped = match clipped.split_by_plane_with_id(plane, (a.faces.len() + face) as u32).0 {
+      Some(behind) => behind,
+      None => return empty,
+    };
+  }
+
+  let face_sources: Vec<FaceSource> = clipped.face_ids.iter()
+    .map(|& id| if (id as usize) < a.faces.len() { FaceSource::A(id as Idx) } else { FaceSource::B(id as Idx - a.faces.len()) })
+    .collect();
+  clipped.face_ids = face_sources.iter()
+    .map(|source| match * source {
+      FaceSource::A(face) => a.face_ids[face],
+      FaceSource::B(face) => b.face_ids[face],
+    })
+    .collect();
+  clipped.vertex_ids = (0..clipped.vertices.len() as u32).collect();
+
+  Intersection { hull: clipped, face_sources: face_sources }
+}
+
+#[cfg(test)]
+mod tests {
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  fn split_volumes(hull: & ConvexPolyhedron, plane: Plane) -> (f32, f32, f32) {
+    match hull.split_by_plane(& plane) {
+      (Some(behind), Some(front), Some(section)) => (behind.volume(), front.volume(), section.area()),
+      _ => panic!("the plane should cut through the hull"),
+    }
+  }
+
+  #[test]
+  fn split_volumes_add_up() {
+    let hull = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 2.0, 0.5)));
+
+    let (behind, front, area) = split_volumes(& hull, Plane::new(Vec3::new(1.0, 0.0, 0.0), 0.5));
+    assert!((behind - 6.0).abs() < 1e-4 && (front - 2.0).abs() < 1e-4);
+    assert!((area - 4.0).abs() < 1e-4);
+
+    let (behind, front, area) = split_volumes(& hull, Plane::from_point_normal(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0)));
+    assert!((behind - 4.0).abs() < 1e-4 && (front - 4.0).abs() < 1e-4);
+    assert!((area - 8.0_f32.sqrt()).abs() < 1e-4);
+
+    let hull = get_convex_polyhedron(ball_points(300, 1));
+    let (behind, front, _) = split_volumes(& hull, Plane::from_point_normal(Pt::new(0.1, 0.2, 0.0), Vec3::new(1.0, -2.0, 0.5)));
+    assert!((behind + front - hull.volume()).abs() < 1e-4);
+  }
+
+  #[test]
+  fn plane_missing_the_hull_keeps_it_whole() {
+    let hull = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    match hull.split_by_plane(& Plane::new(Vec3::new(0.0, 0.0, 1.0), 2.0)) {
+      (Some(behind), None, None) => assert!((behind.volume() - 8.0).abs() < 1e-4),
+      _ => panic!("the whole hull should be behind the plane"),
+    }
+  }
+
+  #[test]
+  fn intersection_volume_of_overlapping_boxes() {
+    let a = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    let b = get_convex_polyhedron(box_corners(Pt::new(1.5, 0.5, 0.0), Vec3::new(1.0, 1.0, 2.0)));
+    let intersection = intersect_hulls(& a, & b);
+    // 0.5 by 1.5 by 2
+    assert!((intersection.hull.volume() - 1.5).abs() < 1e-4);
+    assert_eq!(intersection.face_sources.len(), intersection.hull.faces.len());
+    // The faces are cut from both boxes
+    let from_a = intersection.face_sources.iter().filter(|s| match * * s { FaceSource::A(_) => true, _ => false }).count();
+    assert!(from_a > 0 && from_a < intersection.face_sources.len());
+
+    let apart = get_convex_polyhedron(box_corners(Pt::new(3.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    assert!(intersect_hulls(& a, & apart).hull.is_empty());
+  }
+
+  #[test]
+  fn intersection_is_inside_both_hulls() {
+    let a = get_convex_polyhedron(ball_points(200, 2));
+    let b = get_convex_polyhedron(ball_points(200, 3).into_iter().map(|pt| pt + Vec3::new(0.7, 0.2, -0.1)).collect::<Vec<Pt>>());
+    let intersection = intersect_hulls(& a, & b).hull;
+    assert!(intersection.volume() > 0.0);
+    assert!(intersection.volume() <= a.volume().min(b.volume()));
+    let tolerance = 1e-4;
+    for & v in intersection.vertices.iter() {
+      assert!(a.signed_distance(v) <= tolerance && b.signed_distance(v) <= tolerance);
+    }
+  }
+}
@@ -0,0 +1,615 @@
+use std::f32;
+
+use cgmath;
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::{ConvexPolyhedron, Plane};
+
+// Vertices count as equally far along a direction when they're within this distance of each other,
+// relative to the size of the hull
+const TIE_EPSILON: f32 = 1e-6;
+
+// GJK stops once an iteration improves the distance by less than this fraction
+const GJK_TOLERANCE: f32 = 1e-6;
+const GJK_MAX_ITERATIONS: usize = 64;
+
+// A rigid transform: a rotation followed by a translation
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RigidTransform {
+  pub rotation: Quat,
+  pub translation: Vec3,
+}
+
+impl RigidTransform {
+  pub fn new(rotation: Quat, translation: Vec3) -> RigidTransform {
+    RigidTransform { rotation: rotation, translation: translation }
+  }
+
+  pub fn identity() -> RigidTransform {
+    RigidTransform::new(Quat::one(), Vec3::zero())
+  }
+
+  // Takes the rotation and translation out of a matrix, which should only contain those
+  pub fn from_matrix(mat: & Mat4) -> RigidTransform {
+    let rotation = cgmath::Matrix3::new(
+      mat.x.x, mat.x.y, mat.x.z,
+      mat.y.x, mat.y.y, mat.y.z,
+      mat.z.x, mat.z.y, mat.z.z);
+    RigidTransform::new(Quat::from(rotation).normalize(), mat.w.truncate())
+  }
+
+  pub fn to_matrix(& self) -> Mat4 {
+    Mat4::from_translation(self.translation) * Mat4::from(self.rotation)
+  }
+
+  pub fn apply(& self, pt: Pt) -> Pt {
+    Pt::from_vec(self.rotation.rotate_vector(pt.to_vec()) + self.translation)
+  }
+
+  pub fn apply_vec(& self, vec: Vec3) -> Vec3 {
+    self.rotation.rotate_vector(vec)
+  }
+
+  pub fn inverse_apply_vec(& self, vec: Vec3) -> Vec3 {
+    self.rotation.conjugate().rotate_vector(vec)
+  }
+}
+
+impl ConvexPolyhedron {
+  // Finds the vertex which is farthest along the direction, by climbing from the start vertex
+  // to whichever neighbor is farther along, until no neighbor is. Since the hull is convex,
+  // that's the farthest vertex overall. Starting from a nearby vertex (like the result of the
+  // previous query, for a slowly changing direction) makes this quicker.
+  pub fn support_from(& self, direction: Vec3, start: Idx) -> Idx {
+    let mut current = start;
+    let mut current_dist = self.vertices[current].to_vec().dot(direction);
+    loop {
+      let mut improved = false;
+      for & neighbor in self.vertex_neighbors[current].iter() {
+        let dist = self.vertices[neighbor].to_vec().dot(direction);
+        if dist > current_dist {
+          current = neighbor;
+          current_dist = dist;
+          improved = true;
+        }
+      }
+      if !improved { return current; }
+    }
+  }
+
+  // The index of the vertex farthest along the direction. The hull must not be empty
+  pub fn support(& self, direction: Vec3) -> Idx {
+    self.support_from(direction, 0)
+  }
+
+  pub fn support_point(& self, direction: Vec3) -> Pt {
+    self.vertices[self.support(direction)]
+  }
+
+  // A tolerance for support_set, for vertices which are only told apart by rounding
+  pub fn tie_tolerance(& self) -> f32 {
+    scaled_tolerance(TIE_EPSILON, self.vertices.iter().cloned())
+  }
+
+  // All of the vertices which are as far along the direction as the support vertex, to within the
+  // tolerance, found by spreading out from it to any neighbors which are as far along
+  pub fn support_set(& self, direction: Vec3, tolerance: f32) -> Vec<Idx> {
+    let start = self.support(direction);
+    let limit = self.vertices[start].to_vec().dot(direction) - tolerance;
+    let mut found = vec![start];
+    let mut pending = vec![start];
+    while let Some(current) = pending.pop() {
+      for & neighbor in self.vertex_neighbors[current].iter() {
+        if !found.contains(& neighbor) && self.vertices[neighbor].to_vec().dot(direction) >= limit {
+          found.push(neighbor);
+          pending.push(neighbor);
+        }
+      }
+    }
+    return found;
+  }
+
+  // A copy of the hull, moved by the transform
+  pub fn transformed(& self, transform: & RigidTransform) -> ConvexPolyhedron {
+    let mut moved = self.clone();
+    moved.vertices = self.vertices.iter().map(|& v| transform.apply(v)).collect();
+    moved.planes = self.planes.iter().zip(self.faces.iter())
+      .map(|(plane, face)| Plane::from_point_normal(moved.vertices[face[0]], transform.apply_vec(plane.normal)))
+      .collect();
+    return moved;
+  }
+}
+
+// A hull placed in the world by an optional transform, which can find its support points in world space
+#[derive(Copy, Clone)]
+pub struct PlacedHull<'a> {
+  pub hull: & 'a ConvexPolyhedron,
+  pub transform: Option<RigidTransform>,
+}
+
+impl<'a> PlacedHull<'a> {
+  pub fn new(hull: & 'a ConvexPolyhedron, transform: Option<RigidTransform>) -> PlacedHull<'a> {
+    PlacedHull { hull: hull, transform: transform }
+  }
+
+  // Returns the index of the support vertex and its position in world space
+  pub fn support(& self, direction: Vec3, start: Idx) -> (Idx, Pt) {
+    match self.transform {
+      Some(ref transform) => {
+        let idx = self.hull.support_from(transform.inverse_apply_vec(direction), start);
+        (idx, transform.apply(self.hull.vertices[idx]))
+      },
+      None => {
+        let idx = self.hull.support_from(direction, start);
+        (idx, self.hull.vertices[idx])
+      },
+    }
+  }
+}
+
+// A vertex of the Minkowski difference A - B, along with the hull vertices it was made from.
+// The points are in world space.
+#[derive(Copy, Clone, Debug)]
+pub struct SimplexVertex {
+  pub point: Vec3,
+  pub index_a: Idx,
+  pub index_b: Idx,
+  pub point_a: Pt,
+  pub point_b: Pt,
+}
+
+pub fn minkowski_support(a: & PlacedHull, b: & PlacedHull, direction: Vec3, start: (Idx, Idx)) -> SimplexVertex {
+  let (index_a, point_a) = a.support(direction, start.0);
+  let (index_b, point_b) = b.support(-direction, start.1);
+  SimplexVertex {
+    point: point_a - point_b,
+    index_a: index_a,
+    index_b: index_b,
+    point_a: point_a,
+    point_b: point_b,
+  }
+}
+
+// The result of running GJK on two hulls. The closest points are in world space,
+// and the simplex is the part of the Minkowski difference which the closest points come from.
+// When the hulls overlap, the distance is zero, and the simplex is a tetrahedron containing the origin
+// (unless the hulls only touch).
+#[derive(Clone, Debug)]
+pub struct GjkResult {
+  pub distance: f32,
+  pub intersecting: bool,
+  pub point_a: Pt,
+  pub point_b: Pt,
+  pub simplex: Vec<SimplexVertex>,
+}
+
+// Finds the point of a simplex (of one to four vertices) which is closest to the origin,
+// as barycentric weights. Vertices with zero weight are dropped from the simplex.
+// Returns None when the origin is inside a tetrahedron.
+fn closest_on_simplex(simplex: & [SimplexVertex]) -> Option<Vec<(SimplexVertex, f32)>> {
+  match simplex.len() {
+    1 => Some(vec![(simplex[0], 1.0)]),
+    2 => Some(closest_on_segment(simplex[0], simplex[1])),
+    3 => Some(closest_on_triangle(simplex[0], simplex[1], simplex[2])),
+    _ => closest_on_tetrahedron(simplex),
+  }
+}
+
+fn weighted_sum(weights: & [(SimplexVertex, f32)]) -> Vec3 {
+  weights.iter().fold(Vec3::zero(), |sum, & (vert, weight)| sum + vert.point * weight)
+}
+
+fn closest_on_segment(a: SimplexVertex, b: SimplexVertex) -> Vec<(SimplexVertex, f32)> {
+  let ab = b.point - a.point;
+  let t = -a.point.dot(ab);
+  if t <= 0.0 { return vec![(a, 1.0)]; }
+  let length2 = ab.magnitude2();
+  if t >= length2 { return vec![(b, 1.0)]; }
+  vec![(a, 1.0 - t / length2), (b, t / length2)]
+}
+
+// From Real-Time Collision Detection (Ericson), ClosestPtPointTriangle, with the query point at the origin
+fn closest_on_triangle(a: SimplexVertex, b: SimplexVertex, c: SimplexVertex) -> Vec<(SimplexVertex, f32)> {
+  let ab = b.point - a.point;
+  let ac = c.point - a.point;
+  let ap = -a.point;
+  let d1 = ab.dot(ap);
+  let d2 = ac.dot(ap);
+  if d1 <= 0.0 && d2 <= 0.0 { return vec![(a, 1.0)]; }
+
+  let bp = -b.point;
+  let d3 = ab.dot(bp);
+  let d4 = ac.dot(bp);
+  if d3 >= 0.0 && d4 <= d3 { return vec![(b, 1.0)]; }
+
+  let vc = d1 * d4 - d3 * d2;
+  if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+    let v = d1 / (d1 - d3);
+    return vec![(a, 1.0 - v), (b, v)];
+  }
+
+  let cp = -c.point;
+  let d5 = ab.dot(cp);
+  let d6 = ac.dot(cp);
+  if d6 >= 0.0 && d5 <= d6 { return vec![(c, 1.0)]; }
+
+  let vb = d5 * d2 - d1 * d6;
+  if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+    let w = d2 / (d2 - d6);
+    return vec![(a, 1.0 - w), (c, w)];
+  }
+
+  let va = d3 * d6 - d5 * d4;
+  if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+    let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+    return vec![(b, 1.0 - w), (c, w)];
+  }
+
+  let denom = va + vb + vc;
+  if denom == 0.0 {
+    // The triangle is degenerate, so it's closest along one of its edges
+    let candidates = vec![closest_on_segment(a, b), closest_on_segment(b, c), closest_on_segment(a, c)];
+    return candidates.into_iter()
+      .fold(None, |best: Option<(Vec<(SimplexVertex, f32)>, f32)>, weights| {
+        let dist = weighted_sum(& weights).magnitude2();
+        match best {
+          Some((_, best_dist)) if best_dist <= dist => best,
+          _ => Some((weights, dist)),
+        }
+      })
+      .unwrap().0;
+  }
+  let v = vb / denom;
+  let w = vc / denom;
+  vec![(a, 1.0 - v - w), (b, v), (c, w)]
+}
+
+fn closest_on_tetrahedron(simplex: & [SimplexVertex]) -> Option<Vec<(SimplexVertex, f32)>> {
+  // Each face, with the vertex opposite to it
+  let faces = [(0, 1, 2, 3), (0, 3, 1, 2), (0, 2, 3, 1), (1, 3, 2, 0)];
+
+  let mut best: Option<(Vec<(SimplexVertex, f32)>, f32)> = None;
+  for & (i, j, k, opposite) in faces.iter() {
+    let (a, b, c) = (simplex[i].point, simplex[j].point, simplex[k].point);
+    let normal = (b - a).cross(c - a);
+    let origin_side = normal.dot(-a);
+    let opposite_side = normal.dot(simplex[opposite].point - a);
+    // Only the faces which have the origin on the other side from the rest of the tetrahedron can be closest
+    if origin_side * opposite_side > 0.0 { continue; }
+
+    let weights = closest_on_triangle(simplex[i], simplex[j], simplex[k]);
+    let dist = weighted_sum(& weights).magnitude2();
+    if best.as_ref().map_or(true, |& (_, best_dist)| dist < best_dist) {
+      best = Some((weights, dist));
+    }
+  }
+
+  best.map(|(weights, _)| weights)
+}
+
+// The barycentric weights of the origin inside a tetrahedron, from the volumes of the
+// tetrahedra made by swapping each vertex for the origin
+fn origin_weights(simplex: & [SimplexVertex]) -> Vec<(SimplexVertex, f32)> {
+  let volume = |a: Vec3, b: Vec3, c: Vec3, d: Vec3| (b - a).cross(c - a).dot(d - a);
+  let pts: Vec<Vec3> = simplex.iter().map(|vert| vert.point).collect();
+  let total = volume(pts[0], pts[1], pts[2], pts[3]);
+  if total == 0.0 {
+    return simplex.iter().map(|& vert| (vert, 0.25)).collect();
+  }
+
+  (0..4).map(|i| {
+    let mut replaced = [pts[0], pts[1], pts[2], pts[3]];
+    replaced[i] = Vec3::zero();
+    (simplex[i], volume(replaced[0], replaced[1], replaced[2], replaced[3]) / total)
+  }).collect()
+}
+
+// Computes the distance between two hulls, and their closest points, using the
+// Gilbert-Johnson-Keerthi algorithm. Each hull can be placed with an optional transform.
+// Returns None if either hull is empty.
+pub fn gjk(a: & ConvexPolyhedron, transform_a: Option<RigidTransform>, b: & ConvexPolyhedron, transform_b: Option<RigidTransform>) -> Option<GjkResult> {
+  if a.is_empty() || b.is_empty() { return None; }
+  let placed_a = PlacedHull::new(a, transform_a);
+  let placed_b = PlacedHull::new(b, transform_b);
+
+  let first = minkowski_support(& placed_a, & placed_b, Vec3::unit_x(), (0, 0));
+  let mut weights = vec![(first, 1.0)];
+  let mut closest = first.point;
+
+  for _ in 0..GJK_MAX_ITERATIONS {
+    let closest_dist2 = closest.magnitude2();
+    if closest_dist2 == 0.0 { break; }
+
+    let last = weights[weights.len() - 1].0;
+    let next = minkowski_support(& placed_a, & placed_b, -closest, (last.index_a, last.index_b));
+
+    // Stop when the new point doesn't get any closer to the origin than the current one
+    let already_included = weights.iter().any(|& (vert, _)| vert.index_a == next.index_a && vert.index_b == next.index_b);
+    if already_included || closest_dist2 - closest.dot(next.point) <= GJK_TOLERANCE * closest_dist2 { break; }
+
+    let mut simplex: Vec<SimplexVertex> = weights.iter().map(|& (vert, _)| vert).collect();
+    simplex.push(next);
+    match closest_on_simplex(& simplex) {
+      Some(new_weights) => {
+        let new_closest = weighted_sum(& new_weights);
+        // Guard against numerical trouble making things worse
+        if new_closest.magnitude2() >= closest_dist2 { break; }
+        weights = new_weights;
+        closest = new_closest;
+      },
+      None => {
+        // The origin is inside the tetrahedron, so the hulls overlap
+        weights = origin_weights(& simplex);
+        closest = Vec3::zero();
+        break;
+      },
+    }
+  }
+
+  let distance = closest.magnitude();
+  let intersecting = distance <= GJK_TOLERANCE;
+  let point_a = weights.iter().fold(Vec3::zero(), |sum, & (vert, weight)| sum + vert.point_a.to_vec() * weight);
+  let point_b = weights.iter().fold(Vec3::zero(), |sum, & (vert, weight)| sum + vert.point_b.to_vec() * weight);
+
+  Some(GjkResult {
+    distance: if intersecting { 0.0 } else { distance },
+    intersecting: intersecting,
+    point_a: Pt::from_vec(point_a),
+    point_b: Pt::from_vec(point_b),
+    simplex: weights.into_iter().map(|(vert, _)| vert).collect(),
+  })
+}
+
+// Default termination settings for EPA: it stops once the polytope grows by less than the
+// tolerance in the direction of its closest face, or after the maximum number of iterations
+pub const EPA_TOLERANCE: f32 = 1e-5;
+pub const EPA_MAX_ITERATIONS: usize = 64;
+
+// How deeply two hulls overlap. Moving hull B by normal * depth (or hull A by the opposite)
+// separates them. The contact points are the deepest points of each hull inside the other, in world space.
+// When EPA runs out of iterations before it finds the boundary, converged is false, and the depth is
+// only a lower bound, from the closest face of the polytope it got to.
+#[derive(Copy, Clone, Debug)]
+pub struct Penetration {
+  pub normal: Vec3,
+  pub depth: f32,
+  pub point_a: Pt,
+  pub point_b: Pt,
+  pub converged: bool,
+}
+
+// A triangle of the expanding polytope, with its outward normal and distance from the origin
+#[derive(Copy, Clone, Debug)]
+struct EpaFace {
+  verts: [usize; 3],
+  normal: Vec3,
+  distance: f32,
+}
+
+fn epa_face(polytope: & [SimplexVertex], interior: Vec3, a: usize, b: usize, c: usize) -> Option<EpaFace> {
+  let (pa, pb, pc) = (polytope[a].point, polytope[b].point, polytope[c].point);
+  let cross = (pb - pa).cross(pc - pa);
+  if cross.magnitude2() == 0.0 { return None; }
+  let normal = cross.normalize();
+  // Faces are wound to face away from a point inside the polytope
+  if normal.dot(pa - interior) < 0.0 {
+    Some(EpaFace { verts: [a, c, b], normal: -normal, distance: -normal.dot(pa) })
+  } else {
+    Some(EpaFace { verts: [a, b, c], normal: normal, distance: normal.dot(pa) })
+  }
+}
+
+// GJK can finish with fewer than four points when the hulls are only just touching.
+// Adds support points in directions away from the current simplex until it's a tetrahedron.
+fn fill_simplex(a: & PlacedHull, b: & PlacedHull, simplex: &mut Vec<SimplexVertex>, tolerance: f32) -> bool {
+  let axes = [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()];
+  while simplex.len() < 4 {
+    let mut directions: Vec<Vec3> = Vec::new();
+    match simplex.len() {
+      1 => for axis in axes.iter() { directions.push(* axis); directions.push(-* axis); },
+      2 => {
+        let line = simplex[1].point - simplex[0].point;
+        for axis in axes.iter() {
+          let perpendicular = line.cross(* axis);
+          if perpendicular.magnitude2() > 0.0 { directions.push(perpendicular); directions.push(-perpendicular); }
+        }
+      },
+      _ => {
+        let normal = (simplex[1].point - simplex[0].point).cross(simplex[2].point - simplex[0].point);
+        directions.push(normal);
+        directions.push(-normal);
+      },
+    }
+
+    let found = directions.into_iter()
+      .map(|direction| minkowski_support(a, b, direction, (simplex[0].index_a, simplex[0].index_b)))
+      .find(|candidate| {
+        // The new point needs to be far enough away from the others to give the simplex volume
+        let pts: Vec<Vec3> = simplex.iter().map(|vert| vert.point).collect();
+        match pts.len() {
+          1 => (candidate.point - pts[0]).magnitude() > tolerance,
+          2 => (pts[1] - pts[0]).cross(candidate.point - pts[0]).magnitude() > tolerance * (pts[1] - pts[0]).magnitude(),
+          _ => {
+            let normal = (pts[1] - pts[0]).cross(pts[2] - pts[0]);
+            normal.dot(candidate.point - pts[0]).abs() > tolerance * normal.magnitude()
+          },
+        }
+      });
+
+    match found {
+      Some(vert) => simplex.push(vert),
+      None => return false,
+    }
+  }
+  true
+}
+
+// A direction which a flat Minkowski difference doesn't reach along, given the points fill_simplex found
+// in it. Points have any direction, and lines any direction across them.
+fn flat_normal(simplex: & [SimplexVertex]) -> Vec3 {
+  let normal = match simplex.len() {
+    2 => {
+      let line = simplex[1].point - simplex[0].point;
+      [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()].iter()
+        .map(|& axis| line.cross(axis))
+        .fold(Vec3::zero(), |best, across| if across.magnitude2() > best.magnitude2() { across } else { best })
+    },
+    3 => (simplex[1].point - simplex[0].point).cross(simplex[2].point - simplex[0].point),
+    _ => Vec3::zero(),
+  };
+  if normal.magnitude2() == 0.0 { Vec3::unit_x() } else { normal.normalize() }
+}
+
+// The contact points for the point of a face which is closest to the origin
+fn face_contact(polytope: & [SimplexVertex], face: & EpaFace) -> (Pt, Pt) {
+  let verts: Vec<SimplexVertex> = face.verts.iter().map(|& v| polytope[v]).collect();
+  let projected = face.normal * face.distance;
+  let (v0, v1, v2) = (verts[0].point, verts[1].point, verts[2].point);
+
+  // Barycentric coordinates of the projected origin
+  let (e0, e1, e2) = (v1 - v0, v2 - v0, projected - v0);
+  let (d00, d01, d11, d20, d21) = (e0.dot(e0), e0.dot(e1), e1.dot(e1), e2.dot(e0), e2.dot(e1));
+  let denom = d00 * d11 - d01 * d01;
+  let (u, v, w) = if denom == 0.0 {
+    (1.0, 0.0, 0.0)
+  } else {
+    let v = (d11 * d20 - d01 * d21) / denom;
+    let w = (d00 * d21 - d01 * d20) / denom;
+    (1.0 - v - w, v, w)
+  };
+
+  let point_a = verts[0].point_a.to_vec() * u + verts[1].point_a.to_vec() * v + verts[2].point_a.to_vec() * w;
+  let point_b = verts[0].point_b.to_vec() * u + verts[1].point_b.to_vec() * v + verts[2].point_b.to_vec() * w;
+  (Pt::from_vec(point_a), Pt::from_vec(point_b))
+}
+
+// Finds the penetration depth and direction of two overlapping hulls with the Expanding Polytope Algorithm.
+// Starting from the GJK simplex, the polytope is grown towards the boundary of the Minkowski difference,
+// always in the direction of its face closest to the origin, until that face is on the boundary.
+// Returns None if the hulls don't overlap (or either is empty). When the hulls touch but don't overlap by any
+// volume, which only happens when they're flat in the same plane (or line, or point), the depth is zero and
+// the normal is across that plane.
+pub fn epa(a: & ConvexPolyhedron, transform_a: Option<RigidTransform>, b: & ConvexPolyhedron, transform_b: Option<RigidTransform>, tolerance: f32, max_iterations: usize) -> Option<Penetration> {
+  let gjk_result = gjk(a, transform_a, b, transform_b)?;
+  if !gjk_result.intersecting { return None; }
+
+  let placed_a = PlacedHull::new(a, transform_a);
+  let placed_b = PlacedHull::new(b, transform_b);
+
+  let mut polytope = gjk_result.simplex;
+  if !fill_simplex(& placed_a, & placed_b, &mut polytope, tolerance) {
+    // The Minkowski difference is flat, so the hulls are only touching
+    return Some(Penetration {
+      normal: flat_normal(& polytope),
+      depth: 0.0,
+      point_a: gjk_result.point_a,
+      point_b: gjk_result.point_b,
+      converged: true,
+    });
+  }
+
+  let interior = polytope.iter().fold(Vec3::zero(), |sum, vert| sum + vert.point) / 4.0;
+  let mut faces: Vec<EpaFace> = [(0, 1, 2), (0, 3, 1), (0, 2, 3), (1, 3, 2)].iter()
+    .filter_map(|& (i, j, k)| epa_face(& polytope, interior, i, j, k))
+    .collect();
+
+  let closest_of = |faces: & [EpaFace]| faces.iter().cloned()
+    .fold(faces[0], |closest, face| if face.distance < closest.distance { face } else { closest });
+  let mut closest_face = closest_of(& faces);
+  let mut converged = false;
+  for _ in 0..max_iterations {
+    let last = polytope[closest_face.verts[0]];
+    let next = minkowski_support(& placed_a, & placed_b, closest_face.normal, (last.index_a, last.index_b));
+    // The closest face is on the boundary when the support point is no farther out than the face itself
+    if next.point.dot(closest_face.normal) - closest_face.distance <= tolerance {
+      converged = true;
+      break;
+    }
+
+    polytope.push(next);
+    let new_vert = polytope.len() - 1;
+
+    // Remove the faces which can see the new point, and connect the horizon to it
+    let (visible, kept): (Vec<EpaFace>, Vec<EpaFace>) = faces.into_iter()
+      .partition(|face| face.normal.dot(next.point - polytope[face.verts[0]].point) > 0.0);
+    faces = kept;
+
+    let mut directed_edges: Vec<(usize, usize)> = Vec::new();
+    for face in visible.iter() {
+      for i in 0..3 { directed_edges.push((face.verts[i], face.verts[(i + 1) % 3])); }
+    }
+    for & (from, to) in directed_edges.iter() {
+      if directed_edges.contains(& (to, from)) { continue; }
+      if let Some(face) = epa_face(& polytope, interior, from, to, new_vert) {
+        faces.push(face);
+      }
+    }
+
+    // Rounding can leave no faces to keep going with, and then the last closest face is the best there is
+    if faces.is_empty() { break; }
+    closest_face = closest_of(& faces);
+  }
+
+  let (point_a, point_b) = face_contact(& polytope, & closest_face);
+  Some(Penetration {
+    normal: closest_face.normal,
+    depth: closest_face.distance.max(0.0),
+    point_a: point_a,
+    point_b: point_b,
+    converged: converged,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::Rad;
+
+  use defs::*;
+  use convex_hull::get_convex_polyhedron;
+  use test_support::*;
+  use super::*;
+
+  #[test]
+  fn distance_between_boxes() {
+    let (a, b) = (cube_hull(), cube_hull());
+    let apart = RigidTransform::new(Quat::one(), Vec3::new(3.5, 0.5, -0.25));
+    let result = gjk(& a, None, & b, Some(apart)).unwrap();
+    assert!(!result.intersecting);
+    assert!((result.distance - 1.5).abs() < 1e-4);
+    assert!((result.point_a.x - 1.0).abs() < 1e-4 && (result.point_b.x - 2.5).abs() < 1e-4);
+
+    // Turning B by 45 degrees about z points one of its edges at A
+    let turned = RigidTransform::new(Quat::from_angle_z(Rad(f32::consts::FRAC_PI_4)), Vec3::new(4.0, 0.0, 0.0));
+    let result = gjk(& a, None, & b, Some(turned)).unwrap();
+    assert!((result.distance - (3.0 - f32::consts::SQRT_2)).abs() < 1e-4);
+  }
+
+  #[test]
+  fn penetration_of_boxes() {
+    let (a, b) = (cube_hull(), cube_hull());
+    let overlapping = RigidTransform::new(Quat::one(), Vec3::new(1.6, 0.3, 0.1));
+    let penetration = epa(& a, None, & b, Some(overlapping), EPA_TOLERANCE, EPA_MAX_ITERATIONS).unwrap();
+    assert!(penetration.converged);
+    assert!((penetration.depth - 0.4).abs() < 1e-4);
+    assert!((penetration.normal - Vec3::unit_x()).magnitude() < 1e-4);
+
+    // Stopping before EPA finds the boundary still gives a depth which isn't too deep
+    let early = epa(& a, None, & b, Some(overlapping), EPA_TOLERANCE, 0).unwrap();
+    assert!(!early.converged);
+    assert!(early.depth <= 0.4 + 1e-4);
+
+    let apart = RigidTransform::new(Quat::one(), Vec3::new(2.5, 0.0, 0.0));
+    assert!(epa(& a, None, & b, Some(apart), EPA_TOLERANCE, EPA_MAX_ITERATIONS).is_none());
+  }
+
+  #[test]
+  fn flat_hulls_touching() {
+    // Two overlapping squares in the same plane can be separated by moving either off the plane
+    let square = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0)));
+    let moved = RigidTransform::new(Quat::one(), Vec3::new(0.5, 0.5, 0.0));
+    let penetration = epa(& square, None, & square, Some(moved), EPA_TOLERANCE, EPA_MAX_ITERATIONS).unwrap();
+    assert_eq!(penetration.depth, 0.0);
+    assert!(penetration.normal.z.abs() > 1.0 - 1e-4);
+  }
+}
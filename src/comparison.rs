@@ -0,0 +1,159 @@
+use defs::*;
+use polyhedron::ConvexPolyhedron;
+use clipping::intersect_hulls;
+
+// The farthest a point of one hull is from another hull. Distance from a hull only grows going away
+// from it, so the farthest point is always a vertex, which is given along with the closest point to it
+// on the other hull. When the first hull is inside the other, the distance is zero.
+#[derive(Copy, Clone, Debug)]
+pub struct Deviation {
+  pub distance: f32,
+  pub vertex: Idx,
+  pub closest: Pt,
+}
+
+// How two hulls differ: how far each one sticks out of the other, and how much of their volume they share.
+// The Hausdorff distance is the larger of the two deviations.
+#[derive(Copy, Clone, Debug)]
+pub struct HullComparison {
+  pub hausdorff_distance: f32,
+  pub a_from_b: Deviation,
+  pub b_from_a: Deviation,
+  pub volume_a: f32,
+  pub volume_b: f32,
+  pub intersection_volume: f32,
+  pub symmetric_difference_volume: f32,
+}
+
+// The one sided Hausdorff distance, from the first hull to the second.
+// Returns None if either hull is empty.
+pub fn directed_hausdorff_distance(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> Option<Deviation> {
+  if a.is_empty() || b.is_empty() { return None; }
+
+  let mut farthest = 0;
+  let mut distance = 0.0;
+  for (v, & pt) in a.vertices.iter().enumerate() {
+    let dist = b.signed_distance(pt);
+    if dist > distance {
+      farthest = v;
+      distance = dist;
+    }
+  }
+
+  let vertex = a.vertices[farthest];
+  let closest = if distance > 0.0 { b.closest_point(vertex).map_or(vertex, |closest| closest.point) } else { vertex };
+  Some(Deviation { distance: distance, vertex: farthest, closest: closest })
+}
+
+// The symmetric Hausdorff distance: the farthest any point of either hull is from the other one.
+// Returns None if either hull is empty.
+pub fn hausdorff_distance(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> Option<f32> {
+  let a_from_b = directed_hausdorff_distance(a, b)?;
+  let b_from_a = directed_hausdorff_distance(b, a)?;
+  Some(a_from_b.distance.max(b_from_a.distance))
+}
+
+// The volume inside both hulls. Rounding can make the intersection a little bigger than the hulls
+// it's cut from, so it's limited to the smaller of their volumes
+fn shared_volume(a: & ConvexPolyhedron, b: & ConvexPolyhedron, volume_a: f32, volume_b: f32) -> f32 {
+  intersect_hulls(a, b).hull.volume().min(volume_a).min(volume_b)
+}
+
+// The volume which is inside exactly one of the hulls
+pub fn symmetric_difference_volume(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> f32 {
+  let (volume_a, volume_b) = (a.volume(), b.volume());
+  volume_a + volume_b - 2.0 * shared_volume(a, b, volume_a, volume_b)
+}
+
+// Compares two hulls, for example a scanned part against its model. Returns None if either hull is empty.
+pub fn compare_hulls(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> Option<HullComparison> {
+  let a_from_b = directed_hausdorff_distance(a, b)?;
+  let b_from_a = directed_hausdorff_distance(b, a)?;
+
+  let volume_a = a.volume();
+  let volume_b = b.volume();
+  let intersection_volume = shared_volume(a, b, volume_a, volume_b);
+
+  Some(HullComparison {
+    hausdorff_distance: a_from_b.distance.max(b_from_a.distance),
+    a_from_b: a_from_b,
+    b_from_a: b_from_a,
+    volume_a: volume_a,
+    volume_b: volume_b,
+    intersection_volume: intersection_volume,
+    symmetric_difference_volume: volume_a + volume_b - 2.0 * intersection_volume,
+  })
+}
+
+// The intersection over union of two hulls: the volume inside both, divided by the volume inside either.
+// It's one for identical hulls and zero for hulls which don't overlap, or have no volume between them.
+pub fn hull_iou(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> f32 {
+  if a.is_empty() || b.is_empty() { return 0.0; }
+  let (volume_a, volume_b) = (a.volume(), b.volume());
+  let shared = shared_volume(a, b, volume_a, volume_b);
+  let union = volume_a + volume_b - shared;
+  if union <= 0.0 { 0.0 } else { shared / union }
+}
+
+// Batched version of hull_iou, for each pair of hulls
+pub fn hull_ious(pairs: & [(& ConvexPolyhedron, & ConvexPolyhedron)]) -> Vec<f32> {
+  pairs.iter().map(|& (a, b)| hull_iou(a, b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::prelude::*;
+
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  #[test]
+  fn identical_hulls_fully_overlap() {
+    let hull = get_convex_polyhedron(ball_points(200, 4));
+    assert!((hull_iou(& hull, & hull.clone()) - 1.0).abs() < 1e-4);
+
+    let a = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    let b = get_convex_polyhedron(box_corners(Pt::new(1.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    // Half of each box overlaps: 4 / 12
+    assert!((hull_iou(& a, & b) - 1.0 / 3.0).abs() < 1e-4);
+  }
+
+  #[test]
+  fn disjoint_hulls_do_not_overlap() {
+    let a = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    let apart = get_convex_polyhedron(box_corners(Pt::new(3.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    let touching = get_convex_polyhedron(box_corners(Pt::new(2.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    assert_eq!(hull_ious(& [(& a, & apart), (& a, & touching)]), vec![0.0, 0.0]);
+  }
+
+  #[test]
+  fn box_inside_a_box() {
+    let small = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    let large = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(2.0, 2.0, 2.0)));
+
+    // The small box doesn't stick out of the large one, but the large one's corners are far from the small one
+    assert_eq!(directed_hausdorff_distance(& small, & large).unwrap().distance, 0.0);
+    let deviation = directed_hausdorff_distance(& large, & small).unwrap();
+    assert!((deviation.distance - 3.0_f32.sqrt()).abs() < 1e-4);
+    assert!((large.vertices[deviation.vertex].to_vec().magnitude() - 12.0_f32.sqrt()).abs() < 1e-4);
+    assert!((deviation.closest.to_vec().magnitude() - 3.0_f32.sqrt()).abs() < 1e-4);
+    assert!((hausdorff_distance(& small, & large).unwrap() - 3.0_f32.sqrt()).abs() < 1e-4);
+
+    assert!((symmetric_difference_volume(& small, & large) - 56.0).abs() < 1e-3);
+    let comparison = compare_hulls(& large, & small).unwrap();
+    assert!((comparison.intersection_volume - 8.0).abs() < 1e-3);
+    assert!((comparison.symmetric_difference_volume - 56.0).abs() < 1e-3);
+    assert!((comparison.hausdorff_distance - 3.0_f32.sqrt()).abs() < 1e-4);
+    assert_eq!(comparison.b_from_a.distance, 0.0);
+  }
+
+  #[test]
+  fn identical_hulls_have_no_distance() {
+    let hull = get_convex_polyhedron(ball_points(200, 5));
+    assert!(hausdorff_distance(& hull, & hull.clone()).unwrap() < 1e-5);
+    assert!(symmetric_difference_volume(& hull, & hull.clone()).abs() < 1e-4);
+    assert!(compare_hulls(& hull, & ConvexPolyhedron::empty()).is_none());
+  }
+}
@@ -0,0 +1,294 @@
+use std::f32;
+use std::borrow::Cow;
+
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::{ConvexPolyhedron, Plane};
+use collision::RigidTransform;
+use query::closest_points_on_segments;
+
+// Face axes are preferred over edge axes (and A's faces over B's) unless the other
+// separates the hulls by more than this. This keeps the manifold from flickering between
+// axes which are almost as good as each other
+const AXIS_BIAS: f32 = 1e-3;
+
+// The feature of the hulls which the separating axis came from
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AxisFeature {
+  FaceA(Idx),
+  FaceB(Idx),
+  Edges(Idx, Idx),
+}
+
+// The result of the separating axis test: the axis along which the hulls are the most separated
+// (or the least overlapped), pointing from A towards B. A positive separation means the hulls don't
+// overlap, a negative one is the depth of the overlap along the axis.
+#[derive(Copy, Clone, Debug)]
+pub struct SeparatingAxis {
+  pub axis: Vec3,
+  pub separation: f32,
+  pub feature: AxisFeature,
+}
+
+// A point of contact on the surface of the incident hull, with how deep it is inside the other hull
+#[derive(Copy, Clone, Debug)]
+pub struct ContactPoint {
+  pub point: Pt,
+  pub depth: f32,
+}
+
+// The contact region of two overlapping hulls. The normal points from A towards B
+#[derive(Clone, Debug)]
+pub struct ContactManifold {
+  pub normal: Vec3,
+  pub points: Vec<ContactPoint>,
+  pub feature: AxisFeature,
+}
+
+fn vertex_centroid(hull: & ConvexPolyhedron) -> Pt {
+  Pt::from_vec(hull.vertices.iter().fold(Vec3::zero(), |sum, v| sum + v.to_vec()) / hull.vertices.len() as f32)
+}
+
+// The face of the reference hull which separates the other hull the most
+fn best_face_axis(reference: & ConvexPolyhedron, other: & ConvexPolyhedron) -> (Idx, f32) {
+  reference.planes.iter()
+    .enumerate()
+    .map(|(face, plane)| (face, plane.signed_distance(other.support_point(-plane.normal))))
+    .fold((0, f32::NEG_INFINITY), |best, candidate| if candidate.1 > best.1 { candidate } else { best })
+}
+
+// Checks whether two edges, given by the normals of their adjacent faces, make a face of the Minkowski
+// difference, by checking whether their arcs on the Gauss map cross. Only those pairs of edges need
+// to be tested as separating axes. From Dirk Gregorius' "The Separating Axis Test between Convex Polyhedra"
+pub fn is_minkowski_face(a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> bool {
+  let b_x_a = b.cross(a);
+  let d_x_c = d.cross(c);
+  let cba = c.dot(b_x_a);
+  let dba = d.dot(b_x_a);
+  let adc = a.dot(d_x_c);
+  let bdc = b.dot(d_x_c);
+  cba * dba < 0.0 && adc * bdc < 0.0 && cba * bdc > 0.0
+}
+
+// The pair of edges which separates the hulls the most
+fn best_edge_axis(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> Option<(Idx, Idx, Vec3, f32)> {
+  let center_a = vertex_centroid(a);
+  let tolerance = a.tie_tolerance().max(b.tie_tolerance());
+  let mut best: Option<(Idx, Idx, Vec3, f32)> = None;
+
+  for (edge_a, verts_a) in a.edges.iter().enumerate() {
+    let faces_a = a.edge_faces[edge_a];
+    let (normal_a1, normal_a2) = (a.planes[faces_a[0]].normal, a.planes[faces_a[1]].normal);
+    let (start_a, end_a) = (a.vertices[verts_a[0]], a.vertices[verts_a[1]]);
+
+    for (edge_b, verts_b) in b.edges.iter().enumerate() {
+      let faces_b = b.edge_faces[edge_b];
+      let (normal_b1, normal_b2) = (b.planes[faces_b[0]].normal, b.planes[faces_b[1]].normal);
+      if !is_minkowski_face(normal_a1, normal_a2, -normal_b1, -normal_b2) { continue; }
+
+      let (start_b, end_b) = (b.vertices[verts_b[0]], b.vertices[verts_b[1]]);
+      let cross = (end_a - start_a).cross(end_b - start_b);
+      // Parallel edges don't give a new axis, the face axes already cover them. The edges count as parallel
+      // when the first one's end is within tolerance of the line through its start parallel to the second
+      if cross.magnitude() <= tolerance * (end_b - start_b).magnitude() { continue; }
+      let mut axis = cross.normalize();
+      if axis.dot(start_a - center_a) < 0.0 { axis = -axis; }
+
+      let separation = axis.dot(start_b - start_a);
+      if best.map_or(true, |(_, _, _, best_separation)| separation > best_separation) {
+        best = Some((edge_a, edge_b, axis, separation));
+      }
+    }
+  }
+
+  return best;
+}
+
+// The hull placed in world space. Without a transform it's already there, so it's only borrowed
+fn world_hull<'a>(hull: & 'a ConvexPolyhedron, transform: Option<RigidTransform>) -> Cow<'a, ConvexPolyhedron> {
+  match transform {
+    Some(ref t) => Cow::Owned(hull.transformed(t)),
+    None => Cow::Borrowed(hull),
+  }
+}
+
+fn find_separating_axis(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> SeparatingAxis {
+  let (face_a, separation_a) = best_face_axis(a, b);
+  let (face_b, separation_b) = best_face_axis(b, a);
+
+  let mut result = if separation_b > separation_a + AXIS_BIAS {
+    SeparatingAxis { axis: -b.planes[face_b].normal, separation: separation_b, feature: AxisFeature::FaceB(face_b) }
+  } else {
+    SeparatingAxis { axis: a.planes[face_a].normal, separation: separation_a, feature: AxisFeature::FaceA(face_a) }
+  };
+
+  // Once a face separates the hulls, there's no need to check the edges
+  if result.separation > 0.0 { return result; }
+
+  if let Some((edge_a, edge_b, axis, separation)) = best_edge_axis(a, b) {
+    if separation > result.separation + AXIS_BIAS {
+      result = SeparatingAxis { axis: axis, separation: separation, feature: AxisFeature::Edges(edge_a, edge_b) };
+    }
+  }
+
+  return result;
+}
+
+// Runs the separating axis test on two hulls, each placed by an optional transform.
+// Tests the face normals of both hulls, and the cross products of pairs of edges.
+// Returns None if either hull is empty.
+pub fn separating_axis(a: & ConvexPolyhedron, transform_a: Option<RigidTransform>, b: & ConvexPolyhedron, transform_b: Option<RigidTransform>) -> Option<SeparatingAxis> {
+  if a.is_empty() || b.is_empty() { return None; }
+  Some(find_separating_axis(& world_hull(a, transform_a), & world_hull(b, transform_b)))
+}
+
+// Clips a polygon to the part behind a plane (including any part on it), using Sutherland-Hodgman clipping
+pub(crate) fn clip_polygon(polygon: & [Pt], plane: & Plane) -> Vec<Pt> {
+  let mut clipped = Vec::with_capacity(polygon.len() + 1);
+  for (idx, & current) in polygon.iter().enumerate() {
+    let next = polygon[(idx + 1) % polygon.len()];
+    let (d_current, d_next) = (plane.signed_distance(current), plane.signed_distance(next));
+    if d_current <= 0.0 { clipped.push(current); }
+    if (d_current <= 0.0) != (d_next <= 0.0) {
+      clipped.push(current + (next - current) * (d_current / (d_current - d_next)));
+    }
+  }
+  return clipped;
+}
+
+// Picks up to max_points of the contact points which cover the contact region the best: starting with
+// the deepest one, and repeatedly adding whichever point is farthest from the ones picked so far
+fn reduce_contacts(mut points: Vec<ContactPoint>, max_points: usize) -> Vec<ContactPoint> {
+  if points.len() <= max_points { return points; }
+
+  let mut picked: Vec<ContactPoint> = Vec::with_capacity(max_points);
+  while picked.len() < max_points && !points.is_empty() {
+    let next = if picked.is_empty() {
+      (0..points.len()).fold(0, |best, idx| if points[idx].depth > points[best].depth { idx } else { best })
+    } else {
+      let spread = |pt: & ContactPoint| picked.iter().map(|p| (p.point - pt.point).magnitude2()).fold(f32::MAX, f32::min);
+      (0..points.len()).fold(0, |best, idx| if spread(& points[idx]) > spread(& points[best]) { idx } else { best })
+    };
+    picked.push(points.swap_remove(next));
+  }
+  return picked;
+}
+
+// Clips the face of the incident hull which faces the reference face the most against the sides
+// of the reference face, and keeps the points which are below the reference face.
+fn face_contacts(reference: & ConvexPolyhedron, reference_face: Idx, incident: & ConvexPolyhedron) -> Vec<ContactPoint> {
+  let ref_plane = reference.planes[reference_face];
+  let incident_face = (0..incident.planes.len())
+    .fold(0, |best, face| if incident.planes[face].normal.dot(ref_plane.normal) < incident.planes[best].normal.dot(ref_plane.normal) { face } else { best });
+
+  let mut polygon = incident.face_points(incident_face);
+  let ref_verts = & reference.faces[reference_face];
+  for (idx, & v) in ref_verts.iter().enumerate() {
+    if polygon.is_empty() { break; }
+    let (start, end) = (reference.vertices[v], reference.vertices[ref_verts[(idx + 1) % ref_verts.len()]]);
+    // The face is counterclockwise, so this points out of the face through the edge
+    let side_normal = (end - start).cross(ref_plane.normal).normalize();
+    polygon = clip_polygon(& polygon, & Plane::from_point_normal(start, side_normal));
+  }
+
+  polygon.into_iter()
+    .map(|pt| ContactPoint { point: pt, depth: -ref_plane.signed_distance(pt) })
+    .filter(|contact| contact.depth >= 0.0)
+    .collect()
+}
+
+// Computes the contact manifold of two hulls, each placed by an optional transform. When the separating axis
+// comes from a face, the face of the other hull which is most opposed to it is clipped against it, giving up to
+// max_points contact points on the incident hull. When it comes from a pair of edges, there's a single
+// contact point between them. Returns None when the hulls don't overlap (or either is empty).
+pub fn contact_manifold(a: & ConvexPolyhedron, transform_a: Option<RigidTransform>, b: & ConvexPolyhedron, transform_b: Option<RigidTransform>, max_points: usize) -> Option<ContactManifold> {
+  if a.is_empty() || b.is_empty() { return None; }
+  let world_a = world_hull(a, transform_a);
+  let world_b = world_hull(b, transform_b);
+
+  let axis = find_separating_axis(& world_a, & world_b);
+  if axis.separation > 0.0 { return None; }
+
+  let points = match axis.feature {
+    AxisFeature::FaceA(face) => face_contacts(& world_a, face, & world_b),
+    AxisFeature::FaceB(face) => face_contacts(& world_b, face, & world_a),
+    AxisFeature::Edges(edge_a, edge_b) => {
+      let (start_a, end_a) = (world_a.vertices[world_a.edges[edge_a][0]], world_a.vertices[world_a.edges[edge_a][1]]);
+      let (start_b, end_b) = (world_b.vertices[world_b.edges[edge_b][0]], world_b.vertices[world_b.edges[edge_b][1]]);
+      let (_, on_b) = closest_points_on_segments(start_a, end_a, start_b, end_b);
+      vec![ContactPoint { point: on_b, depth: -axis.separation }]
+    },
+  };
+
+  Some(ContactManifold {
+    normal: axis.axis,
+    points: reduce_contacts(points, max_points),
+    feature: axis.feature,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::Rad;
+
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  #[test]
+  fn box_resting_on_box() {
+    let (a, b) = (cube_hull(), cube_hull());
+    let resting = RigidTransform::new(Quat::one(), Vec3::new(0.5, 1.9, 0.25));
+    let manifold = contact_manifold(& a, None, & b, Some(resting), 4).unwrap();
+    assert!((manifold.normal - Vec3::unit_y()).magnitude() < 1e-4);
+
+    // The bottom of B clipped to the top of A
+    assert_eq!(manifold.points.len(), 4);
+    for contact in manifold.points.iter() {
+      assert!((contact.depth - 0.1).abs() < 1e-4);
+      assert!(contact.point.x >= -0.5 - 1e-4 && contact.point.x <= 1.0 + 1e-4);
+      assert!(contact.point.z >= -0.75 - 1e-4 && contact.point.z <= 1.0 + 1e-4);
+    }
+
+    let apart = RigidTransform::new(Quat::one(), Vec3::new(0.5, 2.1, 0.25));
+    assert!(contact_manifold(& a, None, & b, Some(apart), 4).is_none());
+  }
+
+  #[test]
+  fn crossed_edges() {
+    // A has an edge along x on top, and B has an edge along z underneath, sqrt(2) from their centers
+    let (a, b) = (cube_hull(), cube_hull());
+    let half_turn = Rad(f32::consts::FRAC_PI_4);
+    let placed_a = RigidTransform::new(Quat::from_angle_x(half_turn), Vec3::zero());
+    let placed_b = RigidTransform::new(Quat::from_angle_z(half_turn), Vec3::new(0.2, 2.7, -0.3));
+    let manifold = contact_manifold(& a, Some(placed_a), & b, Some(placed_b), 4).unwrap();
+
+    let depth = 2.0 * f32::consts::SQRT_2 - 2.7;
+    match manifold.feature {
+      AxisFeature::Edges(_, _) => (),
+      feature => panic!("expected an edge contact, got {:?}", feature),
+    }
+    assert!((manifold.normal - Vec3::unit_y()).magnitude() < 1e-4);
+    assert_eq!(manifold.points.len(), 1);
+    assert!((manifold.points[0].depth - depth).abs() < 1e-4);
+    assert!((manifold.points[0].point - Pt::new(0.2, 2.7 - f32::consts::SQRT_2, 0.0)).magnitude() < 1e-4);
+  }
+
+  #[test]
+  fn crossed_edges_at_any_scale() {
+    for & scale in [1e-2, 1e3].iter() {
+      let cube = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(scale, scale, scale)));
+      let half_turn = Rad(f32::consts::FRAC_PI_4);
+      let placed_a = RigidTransform::new(Quat::from_angle_x(half_turn), Vec3::zero());
+      let placed_b = RigidTransform::new(Quat::from_angle_z(half_turn), Vec3::new(0.2, 2.7, -0.3) * scale);
+      let manifold = contact_manifold(& cube, Some(placed_a), & cube, Some(placed_b), 4).unwrap();
+      match manifold.feature {
+        AxisFeature::Edges(_, _) => (),
+        feature => panic!("expected an edge contact at scale {}, got {:?}", scale, feature),
+      }
+      assert!((manifold.points[0].depth - (2.0 * f32::consts::SQRT_2 - 2.7) * scale).abs() < 1e-3 * scale);
+    }
+  }
+}
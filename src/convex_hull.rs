@@ -1,9 +1,15 @@
-use std::collections::LinkedList;
+use std::collections::{LinkedList, HashMap, HashSet};
 
 use cgmath::prelude::*;
 
 use defs::*;
-use half_edge_mesh::{HalfEdgeMesh, FaceRc};
+use polyhedron::ConvexPolyhedron;
+use half_edge_mesh::{HalfEdgeMesh, Vert, Edge, Face, Ptr, FaceRc, ToPtrVec};
+
+// Points only count as being in front of a face when they're farther than this from its plane,
+// relative to the size of the point set. Points which are (almost) coplanar with a face would otherwise
+// be seen by it or not depending on rounding, which can leave a tangled hull behind
+const VISIBILITY_EPSILON: f32 = 2e-6;
 
 // Original Java implementation of this function in comments
 // distSqPointSegment(float[] a, float[] b, float[] c)
@@ -33,10 +39,6 @@ fn triangle_normal(pt1: Pt, pt2: Pt, pt3: Pt) -> Vec3 {
   side_1.cross(side_2).normalize()
 }
 
-fn triangle_center(pt1: Pt, pt2: Pt, pt3: Pt) -> Pt {
-  Pt::from_vec((pt1.to_vec() + pt2.to_vec() + pt3.to_vec()) / 3.0)
-}
-
 #[derive(Copy, Clone, Debug)]
 struct Pair {
   pub idx: usize,
@@ -151,120 +153,643 @@ fn get_extreme_points(list: & Vec<Pt>) -> Vec<usize> {
     }
   }
 
+  // The last point is the one farthest from the plane of the first three. The extreme points
+  // can all be in one plane (like the corners of a box), so the whole list is searched,
+  // otherwise the starting tetrahedron could be flat
   let mut p3 = boundaries[0];
-  let mut tri_dist_sq_max = 0.0;
-  let face_center = triangle_center(p0.pt, p1.pt, p2.pt);
-  for pair in boundaries.iter() {
-    if pair.idx == p0.idx || pair.idx == p1.idx || pair.idx == p2.idx { continue; }
-    let dist = (pair.pt - face_center).magnitude2();
-    if dist > tri_dist_sq_max {
-      tri_dist_sq_max = dist;
-      p3 = pair.clone();
+  let mut plane_dist_max = 0.0;
+  let base_norm = triangle_normal(p0.pt, p1.pt, p2.pt);
+  for (i, pt) in list.iter().cloned().enumerate() {
+    if i == p0.idx || i == p1.idx || i == p2.idx { continue; }
+    let dist = base_norm.dot(pt - p0.pt).abs();
+    if dist > plane_dist_max {
+      plane_dist_max = dist;
+      p3 = Pair::new(i, pt);
     }
   }
 
   return construct_tetrahedron_order(p0, p1, p2, p3);
 }
 
-// Build a convex hull. Takes the points list by move because it needs to mutate the list
-// Either the caller makes a clone of the list to pass in, or the function takes a reference
-// and clones it. Either way, a clone is necessary, and this way the caller can just use a
-// throwaway vector.
-pub fn get_convex_hull(mut points_list: Vec<Pt>) -> HalfEdgeMesh {
-  // Check that we have a valid list of points
-  if points_list.len() < 4 { return HalfEdgeMesh::empty(); }
-  // Get the tetrahedron of the points at maxX, maxY, maxZ, and minZ
-  // These points are on the hull.
-  let mut tet_points = get_extreme_points(& points_list);
-  // This is the starting point of the mesh
-  let mut hull_mesh = HalfEdgeMesh::from_tetrahedron_pts(points_list[tet_points[0]], points_list[tet_points[1]], points_list[tet_points[2]], points_list[tet_points[3]]);
-
-  // Remove them from the list of points, since for performance it's important to minimise the size of this list
-  // let mut remove_indexes: Vec<usize> = (& tet_points).into(); // vec![tet_points[0], tet_points[1], tet_points[2], tet_points[3]];
-  tet_points.sort_by(|a, b| a.cmp(b).reverse());
-  tet_points.dedup();
-  for i in tet_points.into_iter() {
-    // Remove in descending order, to not disturb the indexes of the list
-    if i < points_list.len() {
-      points_list.remove(i);
+// The outcome of a single iteration of the hull construction loop. Faces are referenced
+// by their ids in the hull mesh. The horizon is the closed loop of edges (as pairs of
+// vertex positions) outlining the visible faces, in the order they're connected.
+// When the tested face can't see any of the remaining points, there is no eye point
+// and the rest of the lists are empty. If the mesh couldn't attach the eye point to the
+// horizon, the error it gave is kept, and there are no new faces.
+#[derive(Clone, Debug)]
+pub struct HullStep {
+  pub test_face: u32,
+  pub eye_point: Option<Pt>,
+  pub visible_faces: Vec<u32>,
+  pub horizon: Vec<(Pt, Pt)>,
+  pub new_faces: Vec<u32>,
+  pub error: Option<&'static str>,
+}
+
+impl HullStep {
+  fn empty(test_face: u32) -> HullStep {
+    HullStep {
+      test_face: test_face,
+      eye_point: None,
+      visible_faces: Vec::new(),
+      horizon: Vec::new(),
+      new_faces: Vec::new(),
+      error: None,
+    }
+  }
+}
+
+// Finds the outline of a connected set of faces, by collecting their directed edges
+// and keeping the ones whose opposite edge isn't part of the set.
+// The edges are returned as pairs of vertex ids, ordered so that they form a loop.
+fn faces_outline(faces: & [FaceRc]) -> Vec<(u32, u32)> {
+  let mut directed_edges: HashSet<(u32, u32)> = HashSet::new();
+
+  for face in faces {
+    let verts: Vec<u32> = face.borrow().adjacent_verts().to_ptr_vec().iter().map(|v| v.borrow().id).collect();
+    for (idx, & vert) in verts.iter().enumerate() {
+      directed_edges.insert((vert, verts[(idx + 1) % verts.len()]));
     }
   }
 
-  // Filter the points list based on whether the point is inside the tetrahedron.
-  // This saves a lot of iteration steps. It removes any points from the points list
-  // Which cannot be seen by any face (i.e. they are behind all faces in the tetrahedron)
-  points_list.retain(|p| {
-    hull_mesh.faces.values().any(|f| {
-      f.borrow().can_see(& p)
+  let outline_edges: HashMap<u32, u32> = directed_edges.iter()
+    .filter(|& & (from, to)| !directed_edges.contains(& (to, from)))
+    .cloned()
+    .collect();
+
+  chain_edges(outline_edges)
+}
+
+// Orders a set of directed edges (keyed by their start vertex) into a loop
+pub(crate) fn chain_edges(mut edges: HashMap<u32, u32>) -> Vec<(u32, u32)> {
+  let mut chain = Vec::with_capacity(edges.len());
+
+  let mut current = match edges.keys().min() {
+    Some(& start) => start,
+    None => return chain,
+  };
+
+  while let Some(next) = edges.remove(& current) {
+    chain.push((current, next));
+    current = next;
+  }
+
+  return chain;
+}
+
+fn vertex_positions(mesh: & HalfEdgeMesh, edges: & [(u32, u32)]) -> Vec<(Pt, Pt)> {
+  edges.iter()
+    .filter_map(|& (from, to)| {
+      match (mesh.vertices.get(& from), mesh.vertices.get(& to)) {
+        (Some(a), Some(b)) => Some((a.borrow().pos, b.borrow().pos)),
+        _ => None,
+      }
     })
-  });
+    .collect()
+}
+
+// Builds a convex hull one iteration at a time, so that the construction can be inspected
+// (or animated) as it goes. get_convex_hull just runs the builder until it's done.
+pub struct HullBuilder {
+  hull_mesh: HalfEdgeMesh,
+  points_list: Vec<Pt>,
+  face_queue: LinkedList<FaceRc>,
+  tolerance: f32,
+}
+
+fn can_see(face: & FaceRc, pt: & Pt, tolerance: f32) -> bool {
+  face.borrow().directed_distance_to(pt) > tolerance
+}
+
+impl HullBuilder {
+  // Takes the points list by move because it needs to mutate the list
+  // Either the caller makes a clone of the list to pass in, or the function takes a reference
+  // and clones it. Either way, a clone is necessary, and this way the caller can just use a
+  // throwaway vector.
+  pub fn new(mut points_list: Vec<Pt>) -> HullBuilder {
+    // Check that we have a valid list of points
+    if points_list.len() < 4 {
+      return HullBuilder {
+        hull_mesh: HalfEdgeMesh::empty(),
+        points_list: Vec::new(),
+        face_queue: LinkedList::new(),
+        tolerance: 0.0,
+      };
+    }
+    let tolerance = scaled_tolerance(VISIBILITY_EPSILON, points_list.iter().cloned());
+
+    // Get the tetrahedron of the points at maxX, maxY, maxZ, and minZ
+    // These points are on the hull.
+    let mut tet_points = get_extreme_points(& points_list);
+    // This is the starting point of the mesh
+    let hull_mesh = HalfEdgeMesh::from_tetrahedron_pts(points_list[tet_points[0]], points_list[tet_points[1]], points_list[tet_points[2]], points_list[tet_points[3]]);
+
+    // Remove them from the list of points, since for performance it's important to minimise the size of this list
+    tet_points.sort_by(|a, b| a.cmp(b).reverse());
+    tet_points.dedup();
+    for i in tet_points.into_iter() {
+      // Remove in descending order, to not disturb the indexes of the list
+      if i < points_list.len() {
+        points_list.remove(i);
+      }
+    }
 
-  // Add all faces of the hull to a FIFO queue
-  let mut face_queue: LinkedList<FaceRc> = hull_mesh.faces.values().cloned().collect();
+    // Filter the points list based on whether the point is inside the tetrahedron.
+    // This saves a lot of iteration steps. It removes any points from the points list
+    // Which cannot be seen by any face (i.e. they are behind all faces in the tetrahedron)
+    points_list.retain(|p| {
+      hull_mesh.faces.values().any(|f| can_see(f, p, tolerance))
+    });
+
+    // Add all faces of the hull to a FIFO queue
+    let face_queue: LinkedList<FaceRc> = hull_mesh.faces.values().cloned().collect();
+
+    HullBuilder {
+      hull_mesh: hull_mesh,
+      points_list: points_list,
+      face_queue: face_queue,
+      tolerance: tolerance,
+    }
+  }
+
+  // Starts from a hull which has already been built, to add more points to it. The hull's faces are
+  // all queued up again, but only the points which are outside of the hull are kept, so this is
+  // much quicker than building the hull from scratch when most of the points are inside of it.
+  pub fn from_hull(hull_mesh: HalfEdgeMesh, mut points_list: Vec<Pt>) -> HullBuilder {
+    if hull_mesh.faces.is_empty() {
+      points_list.extend(hull_mesh.vertices.values().map(|v| v.borrow().pos));
+      return HullBuilder::new(points_list);
+    }
 
-  // While the queue has faces, iterate
-  // take a face off the front of the queue
-  while let Some(test_face) = face_queue.pop_front() {
+    let tolerance = scaled_tolerance(VISIBILITY_EPSILON, hull_mesh.vertices.values().map(|v| v.borrow().pos).chain(points_list.iter().cloned()));
+
+    points_list.retain(|p| {
+      hull_mesh.faces.values().any(|f| can_see(f, p, tolerance))
+    });
+
+    let face_queue: LinkedList<FaceRc> = hull_mesh.faces.values().cloned().collect();
+
+    HullBuilder {
+      hull_mesh: hull_mesh,
+      points_list: points_list,
+      face_queue: face_queue,
+      tolerance: tolerance,
+    }
+  }
+
+  // The hull as it has been built so far
+  pub fn mesh(& self) -> & HalfEdgeMesh {
+    & self.hull_mesh
+  }
+
+  // The points which might still end up on the hull
+  pub fn remaining_points(& self) -> & Vec<Pt> {
+    & self.points_list
+  }
+
+  pub fn is_done(& self) -> bool {
+    self.face_queue.is_empty()
+  }
+
+  // The faces which can see the point, along with some of the faces around them which are coplanar with it.
+  // Each edge around the visible faces gets a new face joining it to the point, which lies in the plane
+  // of the coplanar face across the edge. That's only right if the point is outside of the coplanar face,
+  // past that edge. Otherwise the new face would fold back over it, so the coplanar face has to be
+  // replaced as well, which is fine as long as the point is inside all of its other edges.
+  fn visible_region(& self, pt: & Pt) -> Vec<FaceRc> {
+    let mut region: Vec<FaceRc> = Vec::new();
+    let mut region_edges: HashSet<(u32, u32)> = HashSet::new();
+    let mut coplanar: Vec<(FaceRc, Vec<u32>, Vec<bool>)> = Vec::new();
+    for face in self.hull_mesh.faces.values() {
+      let dist = face.borrow().directed_distance_to(pt);
+      if dist < -self.tolerance { continue; }
+
+      let verts = face.borrow().adjacent_verts().to_ptr_vec();
+      let ids: Vec<u32> = verts.iter().map(|v| v.borrow().id).collect();
+      if dist > self.tolerance {
+        for (idx, & id) in ids.iter().enumerate() { region_edges.insert((id, ids[(idx + 1) % ids.len()])); }
+        region.push(face.clone());
+      } else {
+        // Whether the point is outside of each edge, in the plane of the face
+        let normal = face.borrow().normal;
+        let outside = (0..verts.len())
+          .map(|idx| {
+            let (from, to) = (verts[idx].borrow().pos, verts[(idx + 1) % verts.len()].borrow().pos);
+            (to - from).cross(pt - from).dot(normal) < 0.0
+          })
+          .collect();
+        coplanar.push((face.clone(), ids, outside));
+      }
+    }
+
+    // Neighboring faces share an edge, going opposite ways
+    let shares_edge = |ids: & [u32], idx: usize, edges: & HashSet<(u32, u32)>| edges.contains(& (ids[(idx + 1) % ids.len()], ids[idx]));
+
+    // Adding a face can let its neighbors be added, so keep going until nothing changes
+    let mut changed = true;
+    while changed {
+      changed = false;
+      let mut idx = 0;
+      while idx < coplanar.len() {
+        let can_replace = {
+          let (_, ref ids, ref outside) = coplanar[idx];
+          (0..ids.len()).any(|e| shares_edge(ids, e, & region_edges)) &&
+            (0..ids.len()).all(|e| !outside[e] || shares_edge(ids, e, & region_edges))
+        };
+        if can_replace {
+          let (face, ids, _) = coplanar.swap_remove(idx);
+          for (e, & id) in ids.iter().enumerate() { region_edges.insert((id, ids[(e + 1) % ids.len()])); }
+          region.push(face);
+          changed = true;
+        } else {
+          idx += 1;
+        }
+      }
+    }
+
+    // Any coplanar face which the new faces would still fold over is replaced regardless
+    for (face, ids, outside) in coplanar.into_iter() {
+      if (0..ids.len()).any(|e| !outside[e] && shares_edge(& ids, e, & region_edges)) { region.push(face); }
+    }
+    return region;
+  }
+
+  // Performs one iteration of the construction loop, returning what happened,
+  // or None once there are no more faces left to test.
+  pub fn step(&mut self) -> Option<HullStep> {
+    // take a face off the front of the queue
     // Check to make sure it's still in the mesh (many faces will be removed)
-    if !hull_mesh.faces.contains_key(& test_face.borrow().id) { continue; }
-    // For all the points in the list, find the one that is both visible to and farthest from the face
+    let test_face = loop {
+      let face = self.face_queue.pop_front()?;
+      if self.hull_mesh.faces.contains_key(& face.borrow().id) { break face; }
+    };
+    let mut step = HullStep::empty(test_face.borrow().id);
 
-    let face_visible_points: Vec<Pt> = points_list.iter()
-        .filter(|pt| test_face.borrow().can_see(pt))
+    // For all the points in the list, find the one that is both visible to and farthest from the face
+    let face_visible_points: Vec<Pt> = self.points_list.iter()
+        .filter(|pt| can_see(& test_face, pt, self.tolerance))
         .cloned()
         .collect();
 
     let (point_maxima, _) = face_visible_points.iter()
-        .enumerate()
-        .fold((None, 0.0), |(mut point_maxima, mut max_dist), (idx, pt)| {
+        .fold((None, 0.0), |(mut point_maxima, mut max_dist), pt| {
           let dist = test_face.borrow().directed_distance_to(pt);
           if dist > max_dist {
-            point_maxima = Some((idx, pt.clone()));
+            point_maxima = Some(pt.clone());
             max_dist = dist;
           }
           (point_maxima, max_dist)
         });
 
-    if point_maxima.is_none() { continue; }
-    let (max_index, max_point) = point_maxima.unwrap();
+    let max_point = match point_maxima {
+      Some(pt) => pt,
+      None => return Some(step),
+    };
+    step.eye_point = Some(max_point);
 
     // Removes the maximum point from the list of possible points
     // This is essential, because it avoids a certain situation where
     // The algorithm generates new faces which can still see the original maximum point,
     // And these faces then are split, and generate new faces which can still see the
     // Maximum point, along with two invalid faces.
-    // I still don't understand The exact conditions under which this occurs, but try
-    // removing this line and generating a random convex hull about 40 or 50 times, and it'll
-    // probably happen once.
-    points_list.remove(max_index);
+    if let Some(max_index) = self.points_list.iter().position(|p| * p == max_point) {
+      self.points_list.remove(max_index);
+    }
 
     // For all the faces in the mesh, check whether they are visible from the point
     // i.e. check whether the point is in the direction of the face normal,
     // and sufficiently far away that it should count
     // For each face where this is the case, add it to a list.
-    let light_faces: Vec<FaceRc> = hull_mesh.faces.values().filter(|f| f.borrow().can_see(& max_point)).cloned().collect();
+    let light_faces = self.visible_region(& max_point);
+    step.visible_faces = light_faces.iter().map(|f| f.borrow().id).collect();
 
     // These faces should all be adjacent.
     // Find their outline on the mesh. This is the "horizon"
+    step.horizon = vertex_positions(& self.hull_mesh, & faces_outline(& light_faces));
+
     // Then, replace all such faces with new faces which connect
     // To the farthest point.
     // Add the new faces to the end of the queue
-    match hull_mesh.attach_point_for_faces(max_point, & light_faces) {
+    match self.hull_mesh.attach_point_for_faces(max_point, & light_faces) {
       Ok(new_faces) => {
         // Filter out from points_list any point which is
         // in face_visible_points and behind all of the new faces
         // This might be a performance improvement, and it might not. I'm not sure
-        points_list.retain(|p| {
+        let tolerance = self.tolerance;
+        self.points_list.retain(|p| {
           // If the point isn't visible to the current test face, don't worry about it here
-          face_visible_points.iter().all(|face_pt| * face_pt != * p) || new_faces.iter().any(|n_face| n_face.borrow().can_see(p))
+          face_visible_points.iter().all(|face_pt| * face_pt != * p) || new_faces.iter().any(|n_face| can_see(n_face, p, tolerance))
         });
 
-        face_queue.extend(new_faces);
+        step.new_faces = new_faces.iter().map(|f| f.borrow().id).collect();
+        self.face_queue.extend(new_faces);
       },
-      Err(message) => { println!("Error occurred while attaching a new point, {}", message); },
+      Err(message) => { step.error = Some(message); },
     }
+
+    Some(step)
+  }
+
+  // Runs the remaining iterations and returns the completed hull. Errors attaching points
+  // are printed, and the hull is built on without those points.
+  pub fn finish(mut self) -> HalfEdgeMesh {
+    while let Some(step) = self.step() {
+      if let Some(message) = step.error { println!("Error occurred while attaching a new point, {}", message); }
+    }
+    return self.hull_mesh;
+  }
+
+  // Runs the remaining iterations, stopping at the first point which can't be attached,
+  // since the hull might not be convex or closed after that
+  pub fn try_finish(mut self) -> Result<HalfEdgeMesh, &'static str> {
+    while let Some(step) = self.step() {
+      if let Some(message) = step.error { return Err(message); }
+    }
+    Ok(self.hull_mesh)
+  }
+}
+
+// Anything with a position can be put into a hull. Implement this for points which carry
+// other data along with them (colors, labels, etc.), and use get_convex_hull_of to find
+// out which of them ended up on the hull.
+pub trait HullPoint {
+  fn position(& self) -> Pt;
+}
+
+impl HullPoint for Pt {
+  fn position(& self) -> Pt { * self }
+}
+
+impl<'a, P: HullPoint> HullPoint for &'a P {
+  fn position(& self) -> Pt { (* self).position() }
+}
+
+// A hull along with the points it was built from. Each vertex of the hull mesh is a
+// copy of one of the input points' positions, and vertex_sources maps the vertex ids
+// to the indexes of those points.
+pub struct PointHull<P> {
+  pub mesh: HalfEdgeMesh,
+  pub points: Vec<P>,
+  pub vertex_sources: HashMap<u32, Idx>,
+}
+
+impl<P: HullPoint> PointHull<P> {
+  // The point which the given hull vertex came from
+  pub fn source_of(& self, vertex_id: u32) -> Option<& P> {
+    self.vertex_sources.get(& vertex_id).map(|& idx| & self.points[idx])
+  }
+
+  // The points which are vertices of the hull
+  pub fn hull_points(& self) -> Vec<& P> {
+    let mut indexes: Vec<Idx> = self.vertex_sources.values().cloned().collect();
+    indexes.sort();
+    indexes.into_iter().map(|idx| & self.points[idx]).collect()
   }
+}
 
+// Points are identified by their exact bits, since hull vertices are exact copies of the input points
+pub(crate) fn position_key(pt: Pt) -> [u32; 3] {
+  [pt.x.to_bits(), pt.y.to_bits(), pt.z.to_bits()]
+}
+
+// Build a convex hull. The points list is consumed, see HullBuilder::new. Points which can't be
+// attached are reported as in HullBuilder::finish; use try_finish to get the error back instead.
+pub fn get_convex_hull<P: HullPoint>(points_list: Vec<P>) -> HalfEdgeMesh {
+  let positions: Vec<Pt> = points_list.iter().map(|p| p.position()).collect();
   // Once all faces have been iterated over, the convex hull should be complete
-  return hull_mesh;
+  HullBuilder::new(positions).finish()
+}
+
+// A copy of a mesh which keeps all of its vertex, edge and face ids. The mesh hands out new ids by
+// counting up, so the count is run up to each id as it's copied, and later ids won't collide.
+fn copy_mesh(mesh: & HalfEdgeMesh) -> HalfEdgeMesh {
+  let mut copy = HalfEdgeMesh::empty();
+
+  let mut vert_ids: Vec<u32> = mesh.vertices.keys().cloned().collect();
+  vert_ids.sort();
+  for id in vert_ids {
+    while copy.new_vert_id() < id {}
+    copy.push_vert(Ptr::new_rc(Vert::empty(id, mesh.vertices[& id].borrow().pos)));
+  }
+
+  let mut edge_ids: Vec<u32> = mesh.edges.keys().cloned().collect();
+  edge_ids.sort();
+  for & id in edge_ids.iter() {
+    while copy.new_edge_id() < id {}
+    let origin = mesh.edges[& id].borrow().get_origin().expect("Edge without an origin").borrow().id;
+    copy.push_edge(Ptr::new_rc(Edge::with_origin(id, Ptr::new(& copy.vertices[& origin]))));
+  }
+
+  // The faces are added last, once their edges are linked up, since adding a face works out its normal
+  let mut face_ids: Vec<u32> = mesh.faces.keys().cloned().collect();
+  face_ids.sort();
+  let mut faces: HashMap<u32, FaceRc> = HashMap::with_capacity(face_ids.len());
+  for & id in face_ids.iter() {
+    while copy.new_face_id() < id {}
+    let edge = mesh.faces[& id].borrow().get_edge().expect("Face without an edge").borrow().id;
+    let mut face = Face::empty(id);
+    face.set_edge_rc(& copy.edges[& edge]);
+    faces.insert(id, Ptr::new_rc(face));
+  }
+
+  for & id in edge_ids.iter() {
+    let edge = mesh.edges[& id].borrow();
+    let mut copied = copy.edges[& id].borrow_mut();
+    copied.set_next_rc(& copy.edges[& edge.get_next().expect("Edge without a next edge").borrow().id]);
+    copied.set_pair_rc(& copy.edges[& edge.get_pair().expect("Edge without a pair").borrow().id]);
+    copied.set_face_rc(& faces[& edge.get_face().expect("Edge without a face").borrow().id]);
+  }
+  for (id, vert) in mesh.vertices.iter() {
+    let edge = vert.borrow().get_edge().expect("Vertex without an edge").borrow().id;
+    copy.vertices[id].borrow_mut().set_edge_rc(& copy.edges[& edge]);
+  }
+  for id in face_ids {
+    copy.push_face(faces[& id].clone());
+  }
+
+  return copy;
+}
+
+// The hull of two hulls together, using only their vertices. The hull with more vertices is copied and
+// extended with the other one's vertices which are outside of it, so its vertex ids carry over.
+pub fn merge_hulls(a: & HalfEdgeMesh, b: & HalfEdgeMesh) -> HalfEdgeMesh {
+  let (base, other) = if a.vertices.len() >= b.vertices.len() { (a, b) } else { (b, a) };
+  let points: Vec<Pt> = other.vertices.values().map(|v| v.borrow().pos).collect();
+  HullBuilder::from_hull(copy_mesh(base), points).finish()
+}
+
+fn visible_face_rcs(mesh: & HalfEdgeMesh, eye: & Pt) -> Vec<FaceRc> {
+  let tolerance = scaled_tolerance(VISIBILITY_EPSILON, mesh.vertices.values().map(|v| v.borrow().pos));
+  mesh.faces.values().filter(|face| can_see(face, eye, tolerance)).cloned().collect()
+}
+
+// Which part of a hull can be seen from a point outside of it, without changing the hull.
+// It's implemented for the hull meshes, and for hulls which keep track of their points.
+pub trait Visibility {
+  // The ids of the faces of the hull which can be seen from the eye point, sorted.
+  // Faces which are edge on to the eye (to within the same tolerance used while building the hull) aren't visible.
+  fn visible_faces(& self, eye: Pt) -> Vec<u32>;
+
+  // The horizon of the hull seen from the eye point: the closed loop of edges between the visible faces and
+  // the rest, as pairs of vertex ids in order, going counterclockwise around the visible faces.
+  // It's empty if the eye is inside of the hull.
+  fn horizon(& self, eye: Pt) -> Vec<(u32, u32)>;
+}
+
+impl Visibility for HalfEdgeMesh {
+  fn visible_faces(& self, eye: Pt) -> Vec<u32> {
+    let mut ids: Vec<u32> = visible_face_rcs(self, & eye).iter().map(|face| face.borrow().id).collect();
+    ids.sort();
+    return ids;
+  }
+
+  fn horizon(& self, eye: Pt) -> Vec<(u32, u32)> {
+    faces_outline(& visible_face_rcs(self, & eye))
+  }
+}
+
+impl<P: HullPoint> Visibility for PointHull<P> {
+  fn visible_faces(& self, eye: Pt) -> Vec<u32> { self.mesh.visible_faces(eye) }
+
+  fn horizon(& self, eye: Pt) -> Vec<(u32, u32)> { self.mesh.horizon(eye) }
+}
+
+// Build a convex hull as a ConvexPolyhedron, for when the half edge structure isn't needed
+pub fn get_convex_polyhedron<P: HullPoint>(points_list: Vec<P>) -> ConvexPolyhedron {
+  ConvexPolyhedron::from_half_edge_mesh(& get_convex_hull(points_list))
+}
+
+// Build a convex hull, keeping track of which of the input points the hull's vertices came from.
+// If the same position appears more than once, the vertex refers to the first of those points.
+pub fn get_convex_hull_of<P: HullPoint>(points_list: Vec<P>) -> PointHull<P> {
+  let positions: Vec<Pt> = points_list.iter().map(|p| p.position()).collect();
+
+  let mut first_occurrence: HashMap<[u32; 3], Idx> = HashMap::with_capacity(positions.len());
+  for (idx, & pt) in positions.iter().enumerate().rev() {
+    first_occurrence.insert(position_key(pt), idx);
+  }
+
+  let mesh = HullBuilder::new(positions).finish();
+
+  let vertex_sources: HashMap<u32, Idx> = mesh.vertices.values()
+    .filter_map(|v| {
+      let vert = v.borrow();
+      first_occurrence.get(& position_key(vert.pos)).map(|& idx| (vert.id, idx))
+    })
+    .collect();
+
+  PointHull {
+    mesh: mesh,
+    points: points_list,
+    vertex_sources: vertex_sources,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{Rad, Rotation3};
+
+  use defs::*;
+  use test_support::*;
+  use super::*;
+
+  #[test]
+  fn extreme_points_in_one_plane() {
+    // The points which are farthest along each axis are all in one plane here, which used to give a flat
+    // starting tetrahedron. The two points on either side of the plane are never extreme along an axis
+    let points = vec![
+      Pt::new(3.0, -3.0, 0.0), Pt::new(-3.0, 3.0, 0.0), Pt::new(0.0, 3.0, -3.0),
+      Pt::new(0.0, -3.0, 3.0), Pt::new(3.0, 0.0, -3.0), Pt::new(-3.0, 0.0, 3.0),
+      Pt::new(0.5, 0.5, 0.5), Pt::new(-0.5, -0.5, -0.5),
+    ];
+    let hull = get_convex_polyhedron(points);
+    assert_eq!(hull.vertices.len(), 8);
+    // A hexagon with area 27 * sqrt(3), and apexes sqrt(3) / 2 away from it on either side
+    assert!((hull.volume() - 27.0).abs() < 1e-3);
+  }
+
+  #[test]
+  fn rotated_grids_give_boxes() {
+    // Most of the points of a grid are coplanar with faces of the hull as it's built, and once the grid is
+    // rotated, whether they're in front of those faces comes down to rounding
+    for side in 2..6 {
+      for turn in 0..20 {
+        let axis = Vec3::new(1.0, 2.0 + turn as f32 * 0.1, 0.5 * turn as f32).normalize();
+        let rotation = Quat::from_axis_angle(axis, Rad(0.37 * turn as f32));
+        let points: Vec<Pt> = grid_points(side).into_iter().map(|pt| Pt::from_vec(rotation.rotate_vector(pt.to_vec()))).collect();
+        let hull = get_convex_polyhedron(points.clone());
+        assert!(HullBuilder::new(points.clone()).try_finish().is_ok(), "side {} turn {}", side, turn);
+
+        // Points in the middle of an edge can be kept as vertices, but the faces have to be the box's
+        let size = (side - 1) as f32;
+        assert_eq!(hull.faces.len(), 6, "side {} turn {}", side, turn);
+        assert!((hull.volume() - size * size * size).abs() < 1e-3, "side {} turn {}", side, turn);
+        for pt in points.iter() {
+          assert!(hull.planes.iter().all(|plane| plane.signed_distance(* pt) < 1e-4), "side {} turn {}", side, turn);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn merged_hulls_are_copied() {
+    let a = get_convex_hull(grid_points(3));
+    let b = get_convex_hull(grid_points(2).into_iter().map(|pt| pt + Vec3::new(1.5, 1.5, 1.5)).collect::<Vec<Pt>>());
+    let merged = merge_hulls(& a, & b);
+
+    // The hulls given are left as they were, and the bigger one's vertices keep their ids
+    assert!((ConvexPolyhedron::from_half_edge_mesh(& a).volume() - 8.0).abs() < 1e-4);
+    assert!((ConvexPolyhedron::from_half_edge_mesh(& b).volume() - 1.0).abs() < 1e-4);
+    for (id, vert) in a.vertices.iter() {
+      if let Some(kept) = merged.vertices.get(id) { assert_eq!(kept.borrow().pos, vert.borrow().pos); }
+    }
+
+    // The same as the hull of all the points at once
+    let hull = ConvexPolyhedron::from_half_edge_mesh(& merged);
+    let all = get_convex_polyhedron(grid_points(3).into_iter().chain(grid_points(2).into_iter().map(|pt| pt + Vec3::new(1.5, 1.5, 1.5))).collect::<Vec<Pt>>());
+    assert!((hull.volume() - all.volume()).abs() < 1e-4);
+    assert_eq!(hull.vertices.len(), all.vertices.len());
+  }
+
+  #[test]
+  fn horizon_around_a_corner() {
+    // Looking at the corner of a box, the three sides around it can be seen, as two triangles each, and the
+    // horizon goes around all six of their outer edges
+    let mesh = get_convex_hull(grid_points(2));
+    let faces = mesh.faces.len();
+    let eye = Pt::new(3.0, 3.0, 3.0);
+    let visible = mesh.visible_faces(eye);
+    let horizon = mesh.horizon(eye);
+
+    assert_eq!(visible.len(), 6);
+    for id in visible.iter() {
+      assert!(mesh.faces[id].borrow().normal.dot(Vec3::new(1.0, 1.0, 1.0)) > 0.0);
+    }
+    assert_eq!(horizon.len(), 6);
+    for (idx, & (_, to)) in horizon.iter().enumerate() {
+      assert_eq!(to, horizon[(idx + 1) % horizon.len()].0);
+    }
+    assert_eq!(mesh.faces.len(), faces);
+
+    assert!(mesh.visible_faces(Pt::new(0.5, 0.5, 0.5)).is_empty());
+    assert!(mesh.horizon(Pt::new(0.5, 0.5, 0.5)).is_empty());
+  }
+
+  #[test]
+  fn steps_outline_what_they_replace() {
+    let mut builder = HullBuilder::new(grid_points(4).into_iter().map(|pt| pt + Vec3::new(0.1 * pt.y * pt.z, 0.0, 0.05 * pt.x)).collect());
+    while let Some(step) = builder.step() {
+      assert_eq!(step.error, None);
+      if step.eye_point.is_none() { continue; }
+
+      // One new face is made for each edge of the horizon, which is a closed loop
+      assert!(step.horizon.len() >= 3);
+      assert_eq!(step.new_faces.len(), step.horizon.len());
+      for (idx, & (_, to)) in step.horizon.iter().enumerate() {
+        assert_eq!(to, step.horizon[(idx + 1) % step.horizon.len()].0);
+      }
+    }
+    assert!(builder.is_done());
+  }
 }
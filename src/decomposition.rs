@@ -0,0 +1,283 @@
+use std::f32;
+
+use cgmath::prelude::*;
+
+use defs::*;
+use mesh::Mesh;
+use convex_hull::get_convex_hull;
+use polyhedron::{ConvexPolyhedron, Plane};
+use contact::clip_polygon;
+use half_edge_mesh::HalfEdgeMesh;
+
+// Number of candidate cutting planes tried along each axis when splitting a piece
+const PLANE_SAMPLES: usize = 9;
+// Number of planes of the most concave triangles which are tried as cuts as well
+const CONCAVE_PLANES: usize = 8;
+
+// A part of the input surface, along with its convex hull, and how far the surface
+// strays from the hull (the concavity). A convex piece has a concavity of zero.
+struct Piece {
+  triangles: Vec<[Pt; 3]>,
+  hull: HalfEdgeMesh,
+  concavity: f32,
+  splittable: bool,
+}
+
+impl Piece {
+  fn new(triangles: Vec<[Pt; 3]>) -> Piece {
+    let mut points: Vec<Pt> = triangles.iter().flat_map(|tri| tri.iter().cloned()).collect();
+    deduplicate_points(&mut points);
+    let hull = get_convex_hull(points);
+    let concavity = measure_concavity(& triangles, & hull);
+
+    Piece {
+      triangles: triangles,
+      hull: hull,
+      concavity: concavity,
+      splittable: true,
+    }
+  }
+}
+
+// sorts and deduplicates a list of points, since the hull needs distinct points to start from
+fn deduplicate_points(list: &mut Vec<Pt>) {
+  use std::cmp::Ordering::{Less, Equal};
+
+  list.sort_by(|a, b| {
+    match a.x.partial_cmp(& b.x).unwrap_or(Less) {
+      Equal => {
+        match a.y.partial_cmp(& b.y).unwrap_or(Less) {
+          Equal => a.z.partial_cmp(& b.z).unwrap_or(Less),
+          y => y,
+        }
+      },
+      x => x,
+    }
+  });
+
+  list.dedup();
+}
+
+// How far a point is below the surface of a hull. Since the hull is convex, the depth of a point
+// inside it is its distance to the nearest face plane.
+fn depth_below(hull: & HalfEdgeMesh, pt: & Pt) -> f32 {
+  hull.faces.values()
+    .map(|f| -f.borrow().directed_distance_to(pt))
+    .fold(f32::MAX, f32::min)
+    .max(0.0)
+}
+
+// The depth of a triangle below a hull, sampled at its corners and center
+fn triangle_depth(tri: & [Pt; 3], hull: & HalfEdgeMesh) -> f32 {
+  let center = Pt::from_vec((tri[0].to_vec() + tri[1].to_vec() + tri[2].to_vec()) / 3.0);
+  tri.iter().map(|pt| depth_below(hull, pt)).fold(depth_below(hull, & center), f32::max)
+}
+
+// The concavity of a piece is the greatest depth of its surface below its hull
+fn measure_concavity(triangles: & [[Pt; 3]], hull: & HalfEdgeMesh) -> f32 {
+  if hull.faces.is_empty() { return 0.0; }
+  triangles.iter().fold(0.0, |max_depth, tri| max_depth.max(triangle_depth(tri, hull)))
+}
+
+// The planes of the piece's triangles which are deepest below its hull, deepest first, leaving out
+// planes which are the same as one already in the list. Where a shape like an L bends inwards, the
+// surface along the inside of the bend is what separates its convex parts, so cutting along it
+// splits them exactly, which planes in fixed positions would only do by chance.
+fn concave_planes(piece: & Piece) -> Vec<Plane> {
+  if piece.hull.faces.is_empty() { return Vec::new(); }
+  let tolerance = scaled_tolerance(1e-5, piece.triangles.iter().flat_map(|tri| tri.iter().cloned()));
+
+  let mut deepest: Vec<(f32, & [Pt; 3])> = piece.triangles.iter()
+    .map(|tri| (triangle_depth(tri, & piece.hull), tri))
+    .filter(|& (depth, _)| depth > 0.0)
+    .collect();
+  deepest.sort_by(|a, b| b.0.partial_cmp(& a.0).unwrap_or(::std::cmp::Ordering::Equal));
+
+  let mut planes: Vec<Plane> = Vec::new();
+  for (_, tri) in deepest {
+    if planes.len() >= CONCAVE_PLANES { break; }
+    if (tri[1] - tri[0]).cross(tri[2] - tri[0]).magnitude() <= tolerance * tolerance { continue; }
+    let plane = Plane::from_points(tri[0], tri[1], tri[2]);
+    let is_new = planes.iter().all(|other| {
+      (other.normal.dot(plane.normal).abs() < 1.0 - 1e-5) || other.signed_distance(tri[0]).abs() > tolerance
+    });
+    if is_new { planes.push(plane); }
+  }
+  return planes;
+}
+
+fn fan_triangulate(polygon: & [Pt], triangles: &mut Vec<[Pt; 3]>) {
+  for idx in 1..(polygon.len().saturating_sub(1)) {
+    triangles.push([polygon[0], polygon[idx], polygon[idx + 1]]);
+  }
+}
+
+// Splits the triangles of a surface by a plane,
+// cutting the triangles which cross the plane into pieces on either side
+fn split_triangles(triangles: & [[Pt; 3]], plane: & Plane) -> (Vec<[Pt; 3]>, Vec<[Pt; 3]>) {
+  let mut behind = Vec::new();
+  let mut in_front = Vec::new();
+
+  for tri in triangles {
+    if tri.iter().all(|& pt| plane.signed_distance(pt) <= 0.0) { behind.push(* tri); continue; }
+    if tri.iter().all(|& pt| plane.signed_distance(pt) >= 0.0) { in_front.push(* tri); continue; }
+
+    fan_triangulate(& clip_polygon(tri, plane), &mut behind);
+    fan_triangulate(& clip_polygon(tri, & plane.flipped()), &mut in_front);
+  }
+
+  (behind, in_front)
+}
+
+// Finds the cut which produces the least total concavity, out of planes evenly spaced along each axis
+// and the planes of the most concave triangles.
+// Returns None if none of the candidate planes splits the piece in two
+fn best_split(piece: & Piece) -> Option<(Piece, Piece)> {
+  let first = piece.triangles[0][0];
+  let (min, max) = piece.triangles.iter().flat_map(|tri| tri.iter()).fold((first, first), |(min, max), pt| {
+    (Pt::new(min.x.min(pt.x), min.y.min(pt.y), min.z.min(pt.z)), Pt::new(max.x.max(pt.x), max.y.max(pt.y), max.z.max(pt.z)))
+  });
+
+  let mut candidates = concave_planes(piece);
+  for axis in 0..3 {
+    let mut normal = Vec3::zero();
+    normal[axis] = 1.0;
+    for sample in 1..(PLANE_SAMPLES + 1) {
+      let position = min[axis] + (max[axis] - min[axis]) * (sample as f32) / ((PLANE_SAMPLES + 1) as f32);
+      candidates.push(Plane::new(normal, position));
+    }
+  }
+
+  let mut best: Option<(Piece, Piece)> = None;
+  let mut best_cost = f32::MAX;
+
+  for plane in candidates.iter() {
+    let (behind, in_front) = split_triangles(& piece.triangles, plane);
+    if behind.is_empty() || in_front.is_empty() { continue; }
+
+    let (piece_behind, piece_in_front) = (Piece::new(behind), Piece::new(in_front));
+    let cost = piece_behind.concavity + piece_in_front.concavity;
+    if cost < best_cost {
+      best_cost = cost;
+      best = Some((piece_behind, piece_in_front));
+    }
+  }
+
+  return best;
+}
+
+// The pieces which a mesh was decomposed into. Most are convex hulls, but a piece of the surface which
+// is flat (or too small to have four distinct corners) has no volume to hull, so it's kept as triangles
+// instead of being dropped.
+pub struct Decomposition {
+  pub hulls: Vec<HalfEdgeMesh>,
+  pub flat_pieces: Vec<Vec<[Pt; 3]>>,
+}
+
+// Approximately decomposes a triangle mesh into convex pieces, in the manner of V-HACD.
+// The most concave piece is repeatedly cut in two by the plane which minimizes the concavity of the
+// halves, until every piece is within the concavity tolerance, or there are max_pieces pieces.
+// Returns the convex hull of each piece, and the pieces which are flat.
+pub fn get_convex_decomposition(mesh: & Mesh, max_pieces: usize, concavity_tolerance: f32) -> Decomposition {
+  let triangles: Vec<[Pt; 3]> = mesh.index.iter()
+    .map(|tri| [mesh.vert[tri[0]], mesh.vert[tri[1]], mesh.vert[tri[2]]])
+    .collect();
+  let mut decomposition = Decomposition { hulls: Vec::new(), flat_pieces: Vec::new() };
+  if triangles.is_empty() { return decomposition; }
+
+  let mut pieces = vec![Piece::new(triangles)];
+
+  while pieces.len() < max_pieces {
+    let most_concave = pieces.iter()
+      .enumerate()
+      .filter(|& (_, piece)| piece.splittable && piece.concavity > concavity_tolerance)
+      .fold(None, |best: Option<(usize, f32)>, (idx, piece)| {
+        match best {
+          Some((_, concavity)) if concavity >= piece.concavity => best,
+          _ => Some((idx, piece.concavity)),
+        }
+      });
+
+    let split_idx = match most_concave {
+      Some((idx, _)) => idx,
+      None => break,
+    };
+
+    match best_split(& pieces[split_idx]) {
+      Some((below, above)) => {
+        pieces.swap_remove(split_idx);
+        pieces.push(below);
+        pieces.push(above);
+      },
+      None => { pieces[split_idx].splittable = false; },
+    }
+  }
+
+  for piece in pieces {
+    if piece.hull.faces.is_empty() || ConvexPolyhedron::from_half_edge_mesh(& piece.hull).volume() <= 0.0 {
+      decomposition.flat_pieces.push(piece.triangles);
+    } else {
+      decomposition.hulls.push(piece.hull);
+    }
+  }
+  return decomposition;
+}
+
+#[cfg(test)]
+mod tests {
+  use glium::index::PrimitiveType;
+
+  use defs::*;
+  use super::*;
+
+  // The outside surface of a shape made of unit cubes, given by their lowest corners
+  fn cubes_surface(cubes: & [[i32; 3]]) -> Mesh {
+    let mut mesh = Mesh::new(PrimitiveType::TrianglesList);
+    for cube in cubes.iter() {
+      for axis in 0..3 {
+        for & side in [-1, 1].iter() {
+          let mut neighbor = * cube;
+          neighbor[axis] += side;
+          if cubes.contains(& neighbor) { continue; }
+
+          // The two other axes, in the order which makes the square counterclockwise from outside
+          let (first, second) = if side > 0 { ((axis + 1) % 3, (axis + 2) % 3) } else { ((axis + 2) % 3, (axis + 1) % 3) };
+          let start = mesh.vert.len();
+          for & (u, v) in [(0, 0), (1, 0), (1, 1), (0, 1)].iter() {
+            let mut corner = [cube[0] as f32, cube[1] as f32, cube[2] as f32];
+            if side > 0 { corner[axis] += 1.0; }
+            corner[first] += u as f32;
+            corner[second] += v as f32;
+            mesh.add_vert(Pt::new(corner[0], corner[1], corner[2]));
+          }
+          mesh.add_tri([start, start + 1, start + 2]);
+          mesh.add_tri([start, start + 2, start + 3]);
+        }
+      }
+    }
+    return mesh;
+  }
+
+  #[test]
+  fn l_shape_is_cut_along_its_bend() {
+    // The arms are three long, so the bend isn't where any of the evenly spaced planes are
+    let l_shape = cubes_surface(& [[0, 0, 0], [1, 0, 0], [2, 0, 0], [0, 1, 0], [0, 2, 0]]);
+    let decomposition = get_convex_decomposition(& l_shape, 2, 1e-3);
+    assert_eq!(decomposition.hulls.len(), 2);
+    assert!(decomposition.flat_pieces.is_empty());
+    let volumes: Vec<f32> = decomposition.hulls.iter().map(|hull| ConvexPolyhedron::from_half_edge_mesh(hull).volume()).collect();
+    assert!((volumes[0] + volumes[1] - 5.0).abs() < 1e-3, "{:?}", volumes);
+  }
+
+  #[test]
+  fn flat_pieces_are_kept() {
+    let mut square = Mesh::new(PrimitiveType::TrianglesList);
+    for & (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].iter() { square.add_vert(Pt::new(x, y, 0.0)); }
+    square.add_tri([0, 1, 2]);
+    square.add_tri([0, 2, 3]);
+    let decomposition = get_convex_decomposition(& square, 4, 1e-3);
+    assert!(decomposition.hulls.is_empty());
+    assert_eq!(decomposition.flat_pieces.len(), 1);
+    assert_eq!(decomposition.flat_pieces[0].len(), 2);
+  }
+}
@@ -4,5 +4,35 @@ pub type Vec3 = cgmath::Vector3<f32>;
 pub type Pt = cgmath::Point3<f32>;
 pub type Idx = usize;
 pub type Tri = [Idx; 3];
+pub type Mat3 = cgmath::Matrix3<f32>;
 pub type Mat4 = cgmath::Matrix4<f32>;
 pub type Quat = cgmath::Quaternion<f32>;
+
+// A distance tolerance for a set of points: epsilon times the largest coordinate of any of them (or epsilon,
+// for points close to the origin). Rounding errors in positions grow with the size of the coordinates, so
+// checks like "is this point on the plane" need a tolerance which grows with them too.
+pub fn scaled_tolerance<I: IntoIterator<Item = Pt>>(epsilon: f32, points: I) -> f32 {
+  epsilon * points.into_iter().fold(1.0, |scale: f32, pt| scale.max(pt.x.abs()).max(pt.y.abs()).max(pt.z.abs()))
+}
+
+// The root of an element's set in a union-find forest, pointing everything on the way straight at it
+pub(crate) fn find_root(parents: &mut Vec<Idx>, idx: Idx) -> Idx {
+  let mut root = idx;
+  while parents[root] != root { root = parents[root]; }
+  let mut current = idx;
+  while parents[current] != root {
+    let next = parents[current];
+    parents[current] = root;
+    current = next;
+  }
+  return root;
+}
+
+// Joins the sets containing a and b, under the smaller of their roots.
+// Returns false if they were already joined.
+pub(crate) fn union_sets(parents: &mut Vec<Idx>, a: Idx, b: Idx) -> bool {
+  let (root_a, root_b) = (find_root(parents, a), find_root(parents, b));
+  if root_a == root_b { return false; }
+  parents[root_a.max(root_b)] = root_a.min(root_b);
+  return true;
+}
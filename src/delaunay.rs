@@ -0,0 +1,313 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath;
+use cgmath::prelude::*;
+
+use defs::*;
+use mesh::get_tetrahedron_points;
+use convex_hull::position_key;
+
+// The circumsphere tests are done in double precision, since the super tetrahedron is
+// much larger than the point set and single precision runs out of digits quickly.
+type DVec3 = cgmath::Vector3<f64>;
+
+fn to_dvec(pt: Pt) -> DVec3 {
+  DVec3::new(pt.x as f64, pt.y as f64, pt.z as f64)
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Sphere {
+  center: DVec3,
+  radius2: f64,
+}
+
+fn circumsphere(a: DVec3, b: DVec3, c: DVec3, d: DVec3) -> Sphere {
+  let ab = b - a;
+  let ac = c - a;
+  let ad = d - a;
+  let denominator = 2.0 * ab.dot(ac.cross(ad));
+  // Flat tetrahedra have no circumsphere. Treat it as infinitely large,
+  // so that it gets replaced by the next point that's inserted
+  if denominator.abs() < 1e-18 {
+    return Sphere { center: a, radius2: f64::INFINITY };
+  }
+  let offset = (ac.cross(ad) * ab.magnitude2() + ad.cross(ab) * ac.magnitude2() + ab.cross(ac) * ad.magnitude2()) / denominator;
+  Sphere { center: a + offset, radius2: offset.magnitude2() }
+}
+
+// A Delaunay tetrahedralization of a point set. Each tetrahedron is a set of four
+// indexes into the points list, and comes with the radius of its circumsphere
+pub struct Tetrahedralization {
+  pub points: Vec<Pt>,
+  pub tets: Vec<[Idx; 4]>,
+  pub circumradii: Vec<f32>,
+}
+
+impl Tetrahedralization {
+  // Finds the tetrahedra sharing a face with each tetrahedron. The faces of a tetrahedron
+  // are numbered after the vertex opposite to them, so neighbors[t][i] is the tetrahedron
+  // across from tets[t][i], or None when that face is on the convex hull
+  pub fn neighbors(& self) -> Vec<[Option<usize>; 4]> {
+    let mut neighbors = vec![[None; 4]; self.tets.len()];
+    let mut open_faces: HashMap<[Idx; 3], (usize, usize)> = HashMap::new();
+
+    for (t_idx, tet) in self.tets.iter().enumerate() {
+      for opposite in 0..4 {
+        let key = tet_face_key(tet, opposite);
+        match open_faces.remove(& key) {
+          Some((other_tet, other_opposite)) => {
+            neighbors[t_idx][opposite] = Some(other_tet);
+            neighbors[other_tet][other_opposite] = Some(t_idx);
+          },
+          None => { open_faces.insert(key, (t_idx, opposite)); },
+        }
+      }
+    }
+
+    return neighbors;
+  }
+}
+
+// The sorted vertex indexes of the face across from the given vertex of a tetrahedron
+pub fn tet_face_key(tet: & [Idx; 4], opposite: usize) -> [Idx; 3] {
+  let mut key = [0; 3];
+  let mut k = 0;
+  for (i, & v) in tet.iter().enumerate() {
+    if i == opposite { continue; }
+    key[k] = v;
+    k += 1;
+  }
+  key.sort();
+  return key;
+}
+
+// How many times bigger than the point set the super tetrahedron is
+const SUPER_SCALE: f64 = 1e4;
+
+// Six times the signed volume of a tetrahedron, positive when d is on the side of abc that its normal
+// (by the right hand rule) points to. Values this small compared to the edge lengths count as flat.
+const FLAT_EPSILON: f64 = 1e-12;
+
+fn orientation(a: DVec3, b: DVec3, c: DVec3, d: DVec3) -> f64 {
+  let (ab, ac, ad) = (b - a, c - a, d - a);
+  let volume = ab.dot(ac.cross(ad));
+  if volume.abs() <= FLAT_EPSILON * ab.magnitude() * ac.magnitude() * ad.magnitude() { 0.0 } else { volume }
+}
+
+// Whether a point is strictly on the same side of a tetrahedron's face as the vertex across from it
+fn sees_face(points: & [DVec3], tet: & [Idx; 4], opposite: usize, pt: DVec3) -> bool {
+  let face = tet_face_key(tet, opposite);
+  let (a, b, c) = (points[face[0]], points[face[1]], points[face[2]]);
+  let inside = orientation(a, b, c, points[tet[opposite]]);
+  let side = orientation(a, b, c, pt);
+  inside * side > 0.0
+}
+
+// Builds the Delaunay tetrahedralization using the Bowyer-Watson algorithm:
+// each point is inserted by removing the tetrahedra whose circumspheres contain it, and filling the
+// resulting cavity with tetrahedra connecting its boundary to the point. The cavity is grown across faces
+// from the tetrahedron the point is in, which is found by walking towards the point from the newest
+// tetrahedron, so it's always one piece. When points are on the same sphere
+// (as on a grid), rounding can still leave a face of the cavity's boundary which is flat with or facing
+// away from the point, so the tetrahedron beyond it is taken into the cavity too. The new tetrahedra
+// then never overlap or have no volume, at the cost of not being exactly Delaunay among cospherical points.
+// The points start inside of a tetrahedron thousands of times their size, and the tetrahedra connected
+// to it are removed at the end. Points repeating an earlier position are skipped, and aren't part of
+// any tetrahedron. Returns an error if a point isn't finite, or rounding leaves a point outside of
+// every tetrahedron.
+pub fn get_delaunay_tetrahedralization(points_list: & Vec<Pt>) -> Result<Tetrahedralization, &'static str> {
+  let num_points = points_list.len();
+  let mut points: Vec<DVec3> = points_list.iter().cloned().map(to_dvec).collect();
+
+  if points.iter().any(|p| !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite())) {
+    return Err("Points must be finite");
+  }
+  if num_points < 4 {
+    return Ok(Tetrahedralization { points: points_list.clone(), tets: Vec::new(), circumradii: Vec::new() });
+  }
+
+  // Construct a tetrahedron which is much bigger than the point set, so that its corners are outside of
+  // the circumspheres of all but the flattest tetrahedra on the hull of the points. Its size follows the
+  // spread of the points, but isn't allowed below the rounding error of their coordinates, so that points
+  // which are all (nearly) the same still fit inside it.
+  let (min, max) = points.iter().fold((points[0], points[0]), |(min, max), p| {
+    (DVec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)), DVec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)))
+  });
+  let center = (min + max) * 0.5;
+  let spread = (max - min).magnitude().max(scaled_tolerance(FLAT_EPSILON as f32, points_list.iter().cloned()) as f64);
+  let scale = spread * SUPER_SCALE;
+  for pt in get_tetrahedron_points().iter() {
+    points.push(center + to_dvec(* pt) * scale);
+  }
+
+  // Tetrahedra are only marked as removed, so that their indexes stay the same. Each face maps to the
+  // tetrahedra on either side of it
+  let mut tets: Vec<[Idx; 4]> = Vec::new();
+  let mut spheres: Vec<Sphere> = Vec::new();
+  let mut removed: Vec<bool> = Vec::new();
+  let mut face_tets: HashMap<[Idx; 3], Vec<usize>> = HashMap::new();
+  let add_tet = |tet: [Idx; 4], points: & [DVec3], tets: &mut Vec<[Idx; 4]>, spheres: &mut Vec<Sphere>, removed: &mut Vec<bool>, face_tets: &mut HashMap<[Idx; 3], Vec<usize>>| {
+    for opposite in 0..4 {
+      face_tets.entry(tet_face_key(& tet, opposite)).or_insert_with(Vec::new).push(tets.len());
+    }
+    spheres.push(circumsphere(points[tet[0]], points[tet[1]], points[tet[2]], points[tet[3]]));
+    tets.push(tet);
+    removed.push(false);
+  };
+  add_tet([num_points, num_points + 1, num_points + 2, num_points + 3], & points, &mut tets, &mut spheres, &mut removed, &mut face_tets);
+
+  let mut inserted: HashSet<[u32; 3]> = HashSet::with_capacity(num_points);
+  for p_idx in 0..num_points {
+    if !inserted.insert(position_key(points_list[p_idx])) { continue; }
+    let pt = points[p_idx];
+    let across = |face_tets: & HashMap<[Idx; 3], Vec<usize>>, t_idx: usize, key: & [Idx; 3]| {
+      face_tets.get(key).and_then(|sides| sides.iter().cloned().find(|& other| other != t_idx))
+    };
+
+    // Find the tetrahedron which the point is in (or on the boundary of), by walking towards it from the
+    // newest one. Rounding could make the walk go around in circles, so it falls back on checking them all
+    let contains = |t_idx: usize| (0..4).all(|opposite| sees_face(& points, & tets[t_idx], opposite, pt) || {
+      let face = tet_face_key(& tets[t_idx], opposite);
+      orientation(points[face[0]], points[face[1]], points[face[2]], pt) == 0.0
+    });
+    let mut current = tets.len() - 1;
+    for _ in 0..tets.len() {
+      let next = (0..4)
+        .filter(|& opposite| !sees_face(& points, & tets[current], opposite, pt))
+        .filter_map(|opposite| across(& face_tets, current, & tet_face_key(& tets[current], opposite)))
+        .next();
+      match next {
+        Some(other) if !contains(current) => current = other,
+        _ => break,
+      }
+    }
+    let start = if !removed[current] && contains(current) { current } else {
+      match (0..tets.len()).find(|& t_idx| !removed[t_idx] && contains(t_idx)) {
+        Some(t_idx) => t_idx,
+        None => return Err("Point outside of the super tetrahedron"),
+      }
+    };
+
+    let mut cavity = vec![start];
+    removed[start] = true;
+    let mut idx = 0;
+    while idx < cavity.len() {
+      let t_idx = cavity[idx];
+      idx += 1;
+      for opposite in 0..4 {
+        let key = tet_face_key(& tets[t_idx], opposite);
+        if let Some(other) = across(& face_tets, t_idx, & key) {
+          if removed[other] { continue; }
+          let sphere = spheres[other];
+          // Either the point is in the circumsphere, or the cavity wouldn't see the point through this face
+          if (pt - sphere.center).magnitude2() < sphere.radius2 || !sees_face(& points, & tets[t_idx], opposite, pt) {
+            removed[other] = true;
+            cavity.push(other);
+          }
+        }
+      }
+    }
+
+    // The faces on the boundary of the cavity, which the point is connected to
+    let mut new_faces: Vec<[Idx; 3]> = Vec::new();
+    for & t_idx in cavity.iter() {
+      for opposite in 0..4 {
+        let key = tet_face_key(& tets[t_idx], opposite);
+        if across(& face_tets, t_idx, & key).map_or(true, |other| !removed[other]) { new_faces.push(key); }
+      }
+    }
+    for & t_idx in cavity.iter() {
+      for opposite in 0..4 {
+        let key = tet_face_key(& tets[t_idx], opposite);
+        let now_empty = match face_tets.get_mut(& key) {
+          Some(sides) => { sides.retain(|& other| other != t_idx); sides.is_empty() },
+          None => false,
+        };
+        if now_empty { face_tets.remove(& key); }
+      }
+    }
+
+    for face in new_faces {
+      add_tet([face[0], face[1], face[2], p_idx], & points, &mut tets, &mut spheres, &mut removed, &mut face_tets);
+    }
+  }
+
+  // Remove the tetrahedra which are connected to the super tetrahedron
+  let mut result_tets = Vec::with_capacity(tets.len());
+  let mut circumradii = Vec::with_capacity(tets.len());
+  for ((tet, sphere), gone) in tets.into_iter().zip(spheres.into_iter()).zip(removed.into_iter()) {
+    if !gone && tet.iter().all(|& v| v < num_points) {
+      result_tets.push(tet);
+      circumradii.push(sphere.radius2.sqrt() as f32);
+    }
+  }
+
+  Ok(Tetrahedralization {
+    points: points_list.clone(),
+    tets: result_tets,
+    circumradii: circumradii,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::f32;
+
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  fn total_volume(tetrahedralization: & Tetrahedralization) -> f32 {
+    tetrahedralization.tets.iter().map(|tet| {
+      let p = |i: usize| tetrahedralization.points[tet[i]];
+      ((p(1) - p(0)).cross(p(2) - p(0)).dot(p(3) - p(0)) / 6.0).abs()
+    }).sum()
+  }
+
+  #[test]
+  fn grid_with_duplicates_fills_its_hull() {
+    // Every cube of the grid is cospherical, and every point is there twice
+    let mut points = grid_points(4);
+    points.extend(grid_points(4));
+    let delaunay = get_delaunay_tetrahedralization(& points).unwrap();
+    assert!((total_volume(& delaunay) - 27.0).abs() < 1e-3);
+    assert!(delaunay.circumradii.iter().all(|r| r.is_finite()));
+  }
+
+  #[test]
+  fn scattered_points_fill_their_hull() {
+    let points: Vec<Pt> = (0..300).map(|i| {
+      let t = i as f32;
+      Pt::new((t * 0.731).sin() * 3.0, (t * 1.37).cos() * 2.0, (t * 2.113).sin() * (t * 0.29).cos())
+    }).collect();
+    let delaunay = get_delaunay_tetrahedralization(& points).unwrap();
+    let hull_volume = get_convex_polyhedron(points.clone()).volume();
+    assert!((total_volume(& delaunay) - hull_volume).abs() < 1e-4 * hull_volume);
+
+    // No point has another tetrahedron's vertex inside its circumsphere
+    for (tet, & radius) in delaunay.tets.iter().zip(delaunay.circumradii.iter()) {
+      let p = |i: usize| to_dvec(delaunay.points[tet[i]]);
+      let sphere = circumsphere(p(0), p(1), p(2), p(3));
+      assert!((sphere.radius2.sqrt() as f32 - radius).abs() < 1e-3 * radius);
+      for & pt in points.iter() {
+        assert!((to_dvec(pt) - sphere.center).magnitude2() > sphere.radius2 * (1.0 - 1e-6));
+      }
+    }
+  }
+
+  #[test]
+  fn small_set_far_from_the_origin() {
+    let points: Vec<Pt> = ball_points(100, 6).into_iter().map(|pt| Pt::new(100.0, -200.0, 50.0) + pt.to_vec() * 0.1).collect();
+    let delaunay = get_delaunay_tetrahedralization(& points).unwrap();
+    let hull_volume = get_convex_polyhedron(points.clone()).volume();
+    assert!((total_volume(& delaunay) - hull_volume).abs() < 1e-2 * hull_volume);
+  }
+
+  #[test]
+  fn points_which_are_not_finite() {
+    let mut points = box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+    points.push(Pt::new(0.0, f32::NAN, 0.0));
+    assert!(get_delaunay_tetrahedralization(& points).is_err());
+  }
+}
@@ -0,0 +1,152 @@
+use std::f32;
+
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::ConvexPolyhedron;
+use contact::is_minkowski_face;
+
+// The greatest distance between two points of the hull, and the pair of vertices it's between
+#[derive(Copy, Clone, Debug)]
+pub struct Diameter {
+  pub length: f32,
+  pub vertices: [Idx; 2],
+}
+
+// The pair of features which the supporting planes of the width touch
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WidthWitness {
+  FaceVertex(Idx, Idx),
+  EdgeEdge(Idx, Idx),
+}
+
+// The smallest distance between two parallel planes which the hull fits between,
+// along with the direction they face in and the features they touch
+#[derive(Copy, Clone, Debug)]
+pub struct Width {
+  pub width: f32,
+  pub direction: Vec3,
+  pub witness: WidthWitness,
+}
+
+impl ConvexPolyhedron {
+  // The pairs of edges whose Gauss map arcs cross the other's opposite arc.
+  // These are the pairs of edges which can be touched by parallel supporting planes at the same time.
+  fn antipodal_edge_pairs(& self) -> Vec<(Idx, Idx)> {
+    let mut pairs = Vec::new();
+    for edge_a in 0..self.edges.len() {
+      let faces_a = self.edge_faces[edge_a];
+      let (normal_a1, normal_a2) = (self.planes[faces_a[0]].normal, self.planes[faces_a[1]].normal);
+      for edge_b in (edge_a + 1)..self.edges.len() {
+        let faces_b = self.edge_faces[edge_b];
+        if is_minkowski_face(normal_a1, normal_a2, -self.planes[faces_b[0]].normal, -self.planes[faces_b[1]].normal) {
+          pairs.push((edge_a, edge_b));
+        }
+      }
+    }
+    return pairs;
+  }
+
+  // The farthest apart pair of points of the hull. It's always a pair of antipodal vertices, which
+  // are found from each face and the vertices farthest behind it, and from each antipodal pair of edges.
+  // Returns None if the hull is empty.
+  pub fn diameter(& self) -> Option<Diameter> {
+    if self.is_empty() { return None; }
+    let tolerance = self.tie_tolerance();
+
+    let mut best = Diameter { length: 0.0, vertices: [0, 0] };
+    {
+      let mut check = |a: Idx, b: Idx| {
+        let length = (self.vertices[a] - self.vertices[b]).magnitude();
+        if length > best.length { best = Diameter { length: length, vertices: [a, b] }; }
+      };
+
+      for (face, plane) in self.planes.iter().enumerate() {
+        for & opposite in self.support_set(-plane.normal, tolerance).iter() {
+          for & v in self.faces[face].iter() { check(v, opposite); }
+        }
+      }
+
+      for (edge_a, edge_b) in self.antipodal_edge_pairs() {
+        for & a in self.edges[edge_a].iter() {
+          for & b in self.edges[edge_b].iter() { check(a, b); }
+        }
+      }
+    }
+
+    Some(best)
+  }
+
+  // The smallest width of the hull, between parallel supporting planes. Either one of the planes is a face
+  // plane and the other touches the farthest vertex behind it, or the planes touch a pair of antipodal edges.
+  // Returns None if the hull is empty.
+  pub fn width(& self) -> Option<Width> {
+    if self.is_empty() { return None; }
+
+    let mut best = Width { width: f32::MAX, direction: Vec3::zero(), witness: WidthWitness::FaceVertex(0, 0) };
+
+    for (face, plane) in self.planes.iter().enumerate() {
+      let opposite = self.support(-plane.normal);
+      let width = -plane.signed_distance(self.vertices[opposite]);
+      if width < best.width {
+        best = Width { width: width, direction: plane.normal, witness: WidthWitness::FaceVertex(face, opposite) };
+      }
+    }
+
+    let tolerance = self.tie_tolerance();
+    for (edge_a, edge_b) in self.antipodal_edge_pairs() {
+      let (start_a, end_a) = (self.vertices[self.edges[edge_a][0]], self.vertices[self.edges[edge_a][1]]);
+      let (start_b, end_b) = (self.vertices[self.edges[edge_b][0]], self.vertices[self.edges[edge_b][1]]);
+      let cross = (end_a - start_a).cross(end_b - start_b);
+      // Parallel edges, to within the hull's tolerance, are covered by the faces next to them
+      if cross.magnitude() <= tolerance * (end_b - start_b).magnitude() { continue; }
+      let mut direction = cross.normalize();
+      // Face the direction out of the hull at the first edge
+      let faces_a = self.edge_faces[edge_a];
+      if direction.dot(self.planes[faces_a[0]].normal + self.planes[faces_a[1]].normal) < 0.0 { direction = -direction; }
+
+      let width = direction.dot(start_a - start_b);
+      if width < best.width {
+        best = Width { width: width, direction: direction, witness: WidthWitness::EdgeEdge(edge_a, edge_b) };
+      }
+    }
+
+    Some(best)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  #[test]
+  fn box_diameter_and_width() {
+    let hull = get_convex_polyhedron(box_corners(Pt::new(1.0, -2.0, 0.5), Vec3::new(1.0, 2.0, 0.5)));
+    let diameter = hull.diameter().unwrap();
+    assert!((diameter.length - 21.0_f32.sqrt()).abs() < 1e-4);
+    let (a, b) = (hull.vertices[diameter.vertices[0]], hull.vertices[diameter.vertices[1]]);
+    assert!(((a - b).magnitude() - diameter.length).abs() < 1e-4);
+
+    let width = hull.width().unwrap();
+    assert!((width.width - 1.0).abs() < 1e-4);
+    assert!(width.direction.z.abs() > 1.0 - 1e-4);
+  }
+
+  #[test]
+  fn tetrahedron_width_is_between_opposite_edges() {
+    let corners = vec![Pt::new(1.0, 1.0, 1.0), Pt::new(1.0, -1.0, -1.0), Pt::new(-1.0, 1.0, -1.0), Pt::new(-1.0, -1.0, 1.0)];
+    let hull = get_convex_polyhedron(corners);
+    assert!((hull.diameter().unwrap().length - 8.0_f32.sqrt()).abs() < 1e-4);
+
+    // The height over a face is 4 / sqrt(3), more than the distance of 2 between opposite edges
+    let width = hull.width().unwrap();
+    assert!((width.width - 2.0).abs() < 1e-4);
+    match width.witness {
+      WidthWitness::EdgeEdge(_, _) => (),
+      WidthWitness::FaceVertex(_, _) => panic!("the width should be between two edges"),
+    }
+  }
+}
@@ -0,0 +1,492 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::{ConvexPolyhedron, Plane};
+use convex_hull::{get_convex_polyhedron, position_key, chain_edges};
+
+// Vertices are only taken out of a level when they have at most this many neighbors,
+// which limits how many vertices a query has to check at each level
+const MAX_REMOVED_DEGREE: usize = 8;
+// Levels are added until they're this small, and the coarsest level is searched directly
+const COARSEST_SIZE: usize = 8;
+// Identifies the serialized form, and its version
+const MAGIC: &[u8; 4] = b"DKH1";
+
+// One level of the hierarchy. Vertices are referred to by their index in the level, except for the
+// vertices list itself, which gives their index in the points.
+#[derive(Clone, Debug)]
+struct Level {
+  vertices: Vec<Idx>,
+  // Where each vertex of the next coarser level is in this one
+  from_coarser: Vec<Idx>,
+  // The neighbors of each vertex which aren't in the next coarser level. Empty for the coarsest level
+  removed_neighbors: Vec<Vec<Idx>>,
+}
+
+// A Dobkin-Kirkpatrick hierarchy over a convex polyhedron. Each level is the hull of the level before it
+// with an independent set of low degree vertices taken out, so there are O(log n) levels. The farthest
+// vertex along a direction is found by starting with the coarsest level and refining: if the best vertex
+// of a coarser level isn't the best of the finer one, then one of its neighbors which were taken out is,
+// since that neighbor only has neighbors in the coarser level, which aren't as far along.
+#[derive(Clone, Debug)]
+struct Hierarchy {
+  points: Vec<Pt>,
+  levels: Vec<Level>,
+}
+
+// The triangles which fill the hole left by taking a vertex out of a level, given the loop of its neighbors
+// (counterclockwise around it), as indexes into the loop. Since every neighbor is a vertex of the coarser
+// level, the hole is filled by the faces of the neighbors' hull which face the removed vertex. Returns None
+// if those faces don't exactly fill the hole, which rounding can cause when the vertex is almost flat.
+fn fill_hole(removed: Pt, neighbors: & [Pt]) -> Option<Vec<[Idx; 3]>> {
+  let mut triangles = Vec::new();
+  let hull = if neighbors.len() > 3 { get_convex_polyhedron(neighbors.to_vec()) } else { ConvexPolyhedron::empty() };
+  // Three neighbors, or neighbors which are all in a plane, make a flat hole
+  if hull.is_empty() {
+    for idx in 1..(neighbors.len() - 1) { triangles.push([0, idx, idx + 1]); }
+    return Some(triangles);
+  }
+
+  let index_of: HashMap<[u32; 3], Idx> = neighbors.iter().enumerate().map(|(idx, & pt)| (position_key(pt), idx)).collect();
+  // A vertex in the plane of some of its neighbors (like one on the edge of a flat face) is filled in with
+  // that plane's face too
+  let tolerance = hull.tie_tolerance();
+  for (face, plane) in hull.planes.iter().enumerate() {
+    if plane.signed_distance(removed) < -tolerance { continue; }
+    let verts: Vec<Idx> = hull.faces[face].iter().map(|& v| index_of[& position_key(hull.vertices[v])]).collect();
+    for idx in 1..(verts.len() - 1) { triangles.push([verts[0], verts[idx], verts[idx + 1]]); }
+  }
+
+  // The outline of the new triangles has to be the loop of neighbors
+  let mut directed: HashSet<(Idx, Idx)> = HashSet::new();
+  for tri in triangles.iter() {
+    for corner in 0..3 { directed.insert((tri[corner], tri[(corner + 1) % 3])); }
+  }
+  let outline: HashSet<(Idx, Idx)> = directed.iter().cloned().filter(|& (from, to)| !directed.contains(& (to, from))).collect();
+  let expected: HashSet<(Idx, Idx)> = (0..neighbors.len()).map(|idx| (idx, (idx + 1) % neighbors.len())).collect();
+  if outline == expected { Some(triangles) } else { None }
+}
+
+// Splits polygons into triangles, zigzagging between the two ends of each polygon so that no vertex gets
+// more than two new edges. Fanning out from one vertex would give it an edge to every other vertex of the
+// polygon, and a query which reaches a vertex checks all of its neighbors.
+fn strip_triangles(polygons: & [Vec<Idx>]) -> Vec<[Idx; 3]> {
+  let mut triangles = Vec::new();
+  for polygon in polygons.iter() {
+    let (mut low, mut high) = (0, polygon.len() - 1);
+    let mut from_low = true;
+    while high - low >= 2 {
+      if from_low {
+        triangles.push([polygon[low], polygon[low + 1], polygon[high]]);
+        low += 1;
+      } else {
+        triangles.push([polygon[low], polygon[high - 1], polygon[high]]);
+        high -= 1;
+      }
+      from_low = !from_low;
+    }
+  }
+  return triangles;
+}
+
+// The faces of the hull's polar dual, as loops of dual vertices, counterclockwise. Each dual vertex stands for
+// a face of the hull, and each dual face goes around a vertex of the hull. The hull is only convex up to its
+// tolerance, and across an edge which is flat or bends inwards, the two faces' dual vertices can be out of order
+// around the dual faces, which folds the dual over. Faces across edges like that are merged into one dual vertex.
+// Returns the faces which are dual vertices, and the dual faces as indexes into them. If merging would make a
+// dual face go through the same dual vertex twice, nothing is merged.
+fn dual_polygons(hull: & ConvexPolyhedron) -> (Vec<Idx>, Vec<Vec<Idx>>) {
+  let tolerance = hull.tie_tolerance();
+  let in_front = |face: Idx, other: Idx| hull.faces[other].iter()
+    .filter(|v| !hull.faces[face].contains(v))
+    .any(|& v| hull.planes[face].signed_distance(hull.vertices[v]) > -tolerance);
+
+  let mut groups: Vec<Idx> = (0..hull.faces.len()).collect();
+  for sides in hull.edge_faces.iter() {
+    if in_front(sides[0], sides[1]) || in_front(sides[1], sides[0]) { union_sets(&mut groups, sides[0], sides[1]); }
+  }
+
+  let vertex_faces = hull.vertex_faces();
+  let mut loops: Vec<Vec<Idx>> = Vec::with_capacity(vertex_faces.len());
+  for faces in vertex_faces.iter() {
+    let mut around: Vec<Idx> = Vec::with_capacity(faces.len());
+    for & face in faces.iter() {
+      let group = find_root(&mut groups, face);
+      if around.last() != Some(& group) { around.push(group); }
+    }
+    while around.len() > 1 && around[0] == around[around.len() - 1] { around.pop(); }
+
+    let distinct: HashSet<Idx> = around.iter().cloned().collect();
+    if distinct.len() != around.len() { return ((0..hull.faces.len()).collect(), vertex_faces); }
+    // A vertex which is only on two groups is in the middle of an edge between them, and one which is only on
+    // one is inside a group, so neither is a dual face any more
+    if around.len() >= 3 { loops.push(around); }
+  }
+
+  let dual_vertices: Vec<Idx> = (0..hull.faces.len()).filter(|& face| groups[face] == face).collect();
+  let mut dual_index = vec![0; hull.faces.len()];
+  for (idx, & face) in dual_vertices.iter().enumerate() { dual_index[face] = idx; }
+  let loops = loops.into_iter().map(|around| around.into_iter().map(|face| dual_index[face]).collect()).collect();
+  return (dual_vertices, loops);
+}
+
+impl Hierarchy {
+  // Builds the hierarchy for a convex polyhedron, given by which of the points are its vertices and its
+  // surface split into triangles (counterclockwise when seen from outside, indexing into the vertices).
+  // Each coarser level is made by filling in the holes left by the vertices taken out, so it takes O(n)
+  // time per level.
+  fn new(points: Vec<Pt>, mut vertices: Vec<Idx>, mut triangles: Vec<[Idx; 3]>) -> Hierarchy {
+    let mut levels = Vec::new();
+
+    loop {
+      let count = vertices.len();
+
+      // The edges of the triangles opposite each vertex make a loop around it
+      let mut neighbors: Vec<Vec<Idx>> = vec![Vec::new(); count];
+      let mut around: Vec<HashMap<u32, u32>> = vec![HashMap::new(); count];
+      for tri in triangles.iter() {
+        for corner in 0..3 {
+          let (v, next, prev) = (tri[corner], tri[(corner + 1) % 3], tri[(corner + 2) % 3]);
+          if !neighbors[v].contains(& next) { neighbors[v].push(next); }
+          if !neighbors[v].contains(& prev) { neighbors[v].push(prev); }
+          around[v].insert(next as u32, prev as u32);
+        }
+      }
+
+      // Pick an independent set of low degree vertices, and fill in the holes they leave
+      let mut removed = vec![false; count];
+      let mut patches: Vec<[Idx; 3]> = Vec::new();
+      if count > COARSEST_SIZE {
+        let mut blocked = vec![false; count];
+        for v in 0..count {
+          if blocked[v] || neighbors[v].len() > MAX_REMOVED_DEGREE { continue; }
+          let ring: Vec<Idx> = chain_edges(around[v].clone()).into_iter().map(|(from, _)| from as Idx).collect();
+          if ring.len() != neighbors[v].len() { continue; }
+          let ring_points: Vec<Pt> = ring.iter().map(|& n| points[vertices[n]]).collect();
+          if let Some(hole) = fill_hole(points[vertices[v]], & ring_points) {
+            patches.extend(hole.into_iter().map(|tri| [ring[tri[0]], ring[tri[1]], ring[tri[2]]]));
+            removed[v] = true;
+            blocked[v] = true;
+            for & neighbor in neighbors[v].iter() { blocked[neighbor] = true; }
+          }
+        }
+      }
+
+      if !removed.contains(& true) {
+        levels.push(Level { vertices: vertices, from_coarser: Vec::new(), removed_neighbors: Vec::new() });
+        break;
+      }
+
+      let from_coarser: Vec<Idx> = (0..count).filter(|& v| !removed[v]).collect();
+      let mut coarser_index = vec![0; count];
+      for (coarser, & v) in from_coarser.iter().enumerate() { coarser_index[v] = coarser; }
+      let removed_neighbors = (0..count)
+        .map(|v| if removed[v] { Vec::new() } else { neighbors[v].iter().cloned().filter(|& n| removed[n]).collect() })
+        .collect();
+
+      triangles = triangles.iter()
+        .filter(|tri| tri.iter().all(|& v| !removed[v]))
+        .chain(patches.iter())
+        .map(|tri| [coarser_index[tri[0]], coarser_index[tri[1]], coarser_index[tri[2]]])
+        .collect();
+      let coarser_vertices = from_coarser.iter().map(|& v| vertices[v]).collect();
+      levels.push(Level { vertices: vertices, from_coarser: from_coarser, removed_neighbors: removed_neighbors });
+      vertices = coarser_vertices;
+    }
+
+    Hierarchy { points: points, levels: levels }
+  }
+
+  // The index of the point farthest along the direction. There are O(log n) levels, and at each one the
+  // query checks the neighbors of its current vertex which the next coarser level doesn't have. Splitting faces
+  // into strips keeps that to a few, so queries take O(log n) time as long as the polyhedron doesn't have
+  // vertices with many edges. A vertex with k edges, like the tip of a cone, can take O(k) time on its own.
+  fn extreme(& self, direction: Vec3) -> Idx {
+    let dist = |level: & Level, v: Idx| direction.dot(self.points[level.vertices[v]].to_vec());
+
+    let coarsest = & self.levels[self.levels.len() - 1];
+    let mut best = (0..coarsest.vertices.len()).fold(0, |best, v| if dist(coarsest, v) > dist(coarsest, best) { v } else { best });
+
+    for level in self.levels.iter().rev().skip(1) {
+      best = level.from_coarser[best];
+      let mut best_dist = dist(level, best);
+      for & neighbor in level.removed_neighbors[best].iter() {
+        let neighbor_dist = dist(level, neighbor);
+        if neighbor_dist > best_dist {
+          best = neighbor;
+          best_dist = neighbor_dist;
+        }
+      }
+    }
+
+    self.levels[0].vertices[best]
+  }
+
+  // Checks that every index refers to something, so that a deserialized hierarchy can't cause a panic
+  fn is_valid(& self) -> bool {
+    if self.levels.is_empty() { return false; }
+    for (idx, level) in self.levels.iter().enumerate() {
+      if level.vertices.is_empty() || level.vertices.iter().any(|& v| v >= self.points.len()) { return false; }
+      let is_coarsest = idx == self.levels.len() - 1;
+      if is_coarsest { continue; }
+      let coarser_count = self.levels[idx + 1].vertices.len();
+      if level.from_coarser.len() != coarser_count || level.from_coarser.iter().any(|& v| v >= level.vertices.len()) { return false; }
+      if level.removed_neighbors.len() != level.vertices.len() { return false; }
+      if level.removed_neighbors.iter().any(|neighbors| neighbors.iter().any(|& v| v >= level.vertices.len())) { return false; }
+    }
+    return true;
+  }
+}
+
+// A hull preprocessed for answering many queries in logarithmic time: the farthest vertex along a direction,
+// and whether points are inside of it. It doesn't refer to the hull, and can be saved with to_bytes and
+// loaded with from_bytes.
+#[derive(Clone, Debug)]
+pub struct HullHierarchy {
+  center: Pt,
+  planes: Vec<Plane>,
+  vertices: Hierarchy,
+  faces: Hierarchy,
+}
+
+impl HullHierarchy {
+  // Builds the hierarchies for the hull's vertices, and for its faces. Containment is answered using the
+  // polar dual of the hull around its center, whose vertices are the hull's faces: a point is inside the
+  // hull if it isn't past the face whose dual vertex is farthest along the direction to the point.
+  // Faces which the dual merges stand in for each other, which only changes the answer for points within the
+  // hull's tolerance of them. Each face is a dual vertex with an edge per side of the face, so checking a point
+  // near a face with k sides can take O(k) time, as in extreme.
+  // Returns None if the hull is empty or flat.
+  pub fn new(hull: & ConvexPolyhedron) -> Option<HullHierarchy> {
+    if hull.is_empty() || hull.volume() <= 0.0 { return None; }
+    let center = Pt::from_vec(hull.vertices.iter().fold(Vec3::zero(), |sum, v| sum + v.to_vec()) / hull.vertices.len() as f32);
+
+    let mut dual_points = Vec::with_capacity(hull.planes.len());
+    for plane in hull.planes.iter() {
+      let height = -plane.signed_distance(center);
+      if height <= 0.0 { return None; }
+      dual_points.push(Pt::from_vec(plane.normal / height));
+    }
+
+    let (dual_vertices, dual_faces) = dual_polygons(hull);
+
+    Some(HullHierarchy {
+      center: center,
+      planes: hull.planes.clone(),
+      vertices: Hierarchy::new(hull.vertices.clone(), (0..hull.vertices.len()).collect(), strip_triangles(& hull.faces)),
+      faces: Hierarchy::new(dual_points, dual_vertices, strip_triangles(& dual_faces)),
+    })
+  }
+
+  // The index of the hull vertex farthest along the direction
+  pub fn support(& self, direction: Vec3) -> Idx {
+    self.vertices.extreme(direction)
+  }
+
+  pub fn support_point(& self, direction: Vec3) -> Pt {
+    self.vertices.points[self.support(direction)]
+  }
+
+  // A face of the hull which the point is in front of, or None if the point is inside the hull
+  pub fn separating_face(& self, pt: Pt) -> Option<Idx> {
+    let face = self.faces.extreme(pt - self.center);
+    if self.planes[face].signed_distance(pt) > 0.0 { Some(face) } else { None }
+  }
+
+  pub fn contains(& self, pt: Pt) -> bool {
+    self.separating_face(pt).is_none()
+  }
+
+  // The number of levels in the vertex hierarchy
+  pub fn depth(& self) -> usize {
+    self.vertices.levels.len()
+  }
+
+  // Saves the hierarchy as bytes, with all numbers little endian
+  pub fn to_bytes(& self) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    write_point(&mut bytes, self.center);
+    write_u32(&mut bytes, self.planes.len() as u32);
+    for plane in self.planes.iter() {
+      for axis in 0..3 { write_f32(&mut bytes, plane.normal[axis]); }
+      write_f32(&mut bytes, plane.offset);
+    }
+    write_hierarchy(&mut bytes, & self.vertices);
+    write_hierarchy(&mut bytes, & self.faces);
+    return bytes;
+  }
+
+  // Loads a hierarchy saved by to_bytes. Returns None if the bytes aren't a valid hierarchy
+  pub fn from_bytes(bytes: & [u8]) -> Option<HullHierarchy> {
+    if bytes.len() < MAGIC.len() || & bytes[0..MAGIC.len()] != & MAGIC[..] { return None; }
+    let mut reader = Reader { bytes: bytes, position: MAGIC.len() };
+
+    let center = reader.point()?;
+    let plane_count = reader.u32()?;
+    let mut planes = Vec::new();
+    for _ in 0..plane_count {
+      let normal = Vec3::new(reader.f32()?, reader.f32()?, reader.f32()?);
+      planes.push(Plane::new(normal, reader.f32()?));
+    }
+    let vertices = reader.hierarchy()?;
+    let faces = reader.hierarchy()?;
+    if reader.position != bytes.len() { return None; }
+
+    if !vertices.is_valid() || !faces.is_valid() || faces.points.len() != planes.len() { return None; }
+    Some(HullHierarchy { center: center, planes: planes, vertices: vertices, faces: faces })
+  }
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+  for shift in 0..4 { bytes.push((value >> (shift * 8)) as u8); }
+}
+
+fn write_f32(bytes: &mut Vec<u8>, value: f32) {
+  write_u32(bytes, value.to_bits());
+}
+
+fn write_point(bytes: &mut Vec<u8>, pt: Pt) {
+  for axis in 0..3 { write_f32(bytes, pt[axis]); }
+}
+
+fn write_indexes(bytes: &mut Vec<u8>, indexes: & [Idx]) {
+  write_u32(bytes, indexes.len() as u32);
+  for & idx in indexes.iter() { write_u32(bytes, idx as u32); }
+}
+
+fn write_hierarchy(bytes: &mut Vec<u8>, hierarchy: & Hierarchy) {
+  write_u32(bytes, hierarchy.points.len() as u32);
+  for & pt in hierarchy.points.iter() { write_point(bytes, pt); }
+  write_u32(bytes, hierarchy.levels.len() as u32);
+  for level in hierarchy.levels.iter() {
+    write_indexes(bytes, & level.vertices);
+    write_indexes(bytes, & level.from_coarser);
+    write_u32(bytes, level.removed_neighbors.len() as u32);
+    for neighbors in level.removed_neighbors.iter() { write_indexes(bytes, neighbors); }
+  }
+}
+
+// Reads back what the write functions wrote. Each read returns None once the bytes run out
+struct Reader<'a> {
+  bytes: & 'a [u8],
+  position: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn u32(&mut self) -> Option<u32> {
+    if self.position + 4 > self.bytes.len() { return None; }
+    let value = (0..4).fold(0, |value, idx| value | (self.bytes[self.position + idx] as u32) << (idx * 8));
+    self.position += 4;
+    Some(value)
+  }
+
+  fn f32(&mut self) -> Option<f32> {
+    self.u32().map(f32::from_bits)
+  }
+
+  fn point(&mut self) -> Option<Pt> {
+    Some(Pt::new(self.f32()?, self.f32()?, self.f32()?))
+  }
+
+  fn indexes(&mut self) -> Option<Vec<Idx>> {
+    let count = self.u32()?;
+    let mut indexes = Vec::new();
+    for _ in 0..count { indexes.push(self.u32()? as Idx); }
+    Some(indexes)
+  }
+
+  fn hierarchy(&mut self) -> Option<Hierarchy> {
+    let point_count = self.u32()?;
+    let mut points = Vec::new();
+    for _ in 0..point_count { points.push(self.point()?); }
+
+    let level_count = self.u32()?;
+    let mut levels = Vec::new();
+    for _ in 0..level_count {
+      let vertices = self.indexes()?;
+      let from_coarser = self.indexes()?;
+      let neighbor_count = self.u32()?;
+      let mut removed_neighbors = Vec::new();
+      for _ in 0..neighbor_count { removed_neighbors.push(self.indexes()?); }
+      levels.push(Level { vertices: vertices, from_coarser: from_coarser, removed_neighbors: removed_neighbors });
+    }
+
+    Some(Hierarchy { points: points, levels: levels })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{Rng, SeedableRng};
+  use rand::rngs::StdRng;
+
+  use defs::*;
+  use test_support::*;
+  use super::*;
+
+  fn brute_force_support(hull: & ConvexPolyhedron, direction: Vec3) -> f32 {
+    hull.vertices.iter().map(|v| direction.dot(v.to_vec())).fold(::std::f32::MIN, f32::max)
+  }
+
+  fn check_queries(hull: & ConvexPolyhedron, seed: u64) {
+    let hierarchy = HullHierarchy::new(hull).unwrap();
+    let size = hull.vertices.iter().map(|v| v.to_vec().magnitude()).fold(0.0, f32::max);
+    let tolerance = 1e-5 * size;
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..2000 {
+      let direction = Vec3::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0));
+      let support = direction.dot(hierarchy.support_point(direction).to_vec());
+      assert!(support >= brute_force_support(hull, direction) - tolerance);
+
+      // Points right on the surface could go either way
+      let pt = Pt::from_vec(Vec3::new(rng.gen_range(-1.2, 1.2), rng.gen_range(-1.2, 1.2), rng.gen_range(-1.2, 1.2)) * size);
+      let distance = hull.planes.iter().map(|plane| plane.signed_distance(pt)).fold(::std::f32::MIN, f32::max);
+      if distance.abs() > tolerance { assert_eq!(hierarchy.contains(pt), distance < 0.0); }
+    }
+  }
+
+  #[test]
+  fn queries_match_brute_force() {
+    for seed in 0..5 {
+      check_queries(& get_convex_polyhedron(ball_points(1000, seed)), seed);
+      // All of the points are on the hull, and many of its faces are almost flat with their neighbors
+      let sphere: Vec<Pt> = ball_points(1500, seed).into_iter().map(|pt| Pt::from_vec(pt.to_vec().normalize() * 3.0)).collect();
+      check_queries(& get_convex_polyhedron(sphere), seed);
+    }
+  }
+
+  #[test]
+  fn faces_with_many_sides() {
+    let sides = 200;
+    let mut points = Vec::new();
+    for idx in 0..sides {
+      let angle = idx as f32 / sides as f32 * 2.0 * ::std::f32::consts::PI;
+      points.push(Pt::new(angle.cos() * 2.0, angle.sin() * 2.0, -1.0));
+      points.push(Pt::new(angle.cos() * 2.0, angle.sin() * 2.0, 1.0));
+    }
+    let prism = get_convex_polyhedron(points);
+    check_queries(& prism, 7);
+
+    // The caps are split into strips rather than fans, so no vertex has many neighbors to check
+    let hierarchy = HullHierarchy::new(& prism).unwrap();
+    for level in hierarchy.vertices.levels.iter() {
+      assert!(level.removed_neighbors.iter().all(|neighbors| neighbors.len() <= MAX_REMOVED_DEGREE));
+    }
+    assert!(hierarchy.depth() > 5);
+  }
+
+  #[test]
+  fn saved_and_loaded() {
+    let hull = get_convex_polyhedron(ball_points(300, 3));
+    let hierarchy = HullHierarchy::new(& hull).unwrap();
+    let bytes = hierarchy.to_bytes();
+    let loaded = HullHierarchy::from_bytes(& bytes).unwrap();
+    assert!(HullHierarchy::from_bytes(& bytes[..bytes.len() - 1]).is_none());
+    for & direction in [Vec3::new(1.0, 0.3, -0.2), Vec3::new(-0.5, 0.1, 0.9)].iter() {
+      assert_eq!(loaded.support(direction), hierarchy.support(direction));
+      assert_eq!(loaded.contains(Pt::from_vec(direction * 0.9)), hierarchy.contains(Pt::from_vec(direction * 0.9)));
+    }
+  }
+}
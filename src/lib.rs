@@ -3,9 +3,42 @@ extern crate glium;
 extern crate cgmath;
 extern crate itertools;
 extern crate half_edge_mesh;
+#[cfg(test)]
+extern crate rand;
 
 pub mod bufferset;
 pub mod defs;
 pub mod mesh;
 pub mod convex_hull;
-pub use convex_hull::get_convex_hull;
+pub mod polyhedron;
+pub mod query;
+pub mod collision;
+pub mod contact;
+pub mod mass;
+pub mod bounding_box;
+pub mod bounding_sphere;
+pub mod extents;
+pub mod clipping;
+pub mod minkowski;
+pub mod silhouette;
+pub mod hierarchy;
+pub mod offset;
+pub mod comparison;
+pub mod delaunay;
+pub mod alpha_shape;
+pub mod decomposition;
+#[cfg(test)]
+mod test_support;
+pub use convex_hull::{get_convex_hull, get_convex_hull_of, get_convex_polyhedron, merge_hulls, HullBuilder, HullStep, HullPoint, PointHull, Visibility};
+pub use polyhedron::{ConvexPolyhedron, Plane};
+pub use query::{Containment, Feature, ClosestPoint, RayHit};
+pub use collision::{RigidTransform, gjk, epa};
+pub use contact::{separating_axis, contact_manifold, SeparatingAxis, AxisFeature, ContactManifold, ContactPoint};
+pub use bounding_box::OrientedBox;
+pub use bounding_sphere::{get_bounding_sphere, get_minimum_enclosing_sphere, BoundingSphere};
+pub use extents::{Diameter, Width, WidthWitness};
+pub use clipping::{intersect_hulls, CrossSection, Intersection, FaceSource};
+pub use minkowski::{minkowski_sum, minkowski_difference};
+pub use silhouette::{View, Silhouette};
+pub use hierarchy::HullHierarchy;
+pub use comparison::{directed_hausdorff_distance, hausdorff_distance, symmetric_difference_volume, compare_hulls, hull_iou, hull_ious, Deviation, HullComparison};
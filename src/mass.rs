@@ -0,0 +1,152 @@
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::ConvexPolyhedron;
+
+// The integrals over the solid hull, relative to a reference point on it: the volume, the first
+// moment (volume times the centroid's offset) and the second moment, or covariance, matrix
+struct Moments {
+  reference: Pt,
+  volume: f32,
+  first: Vec3,
+  second: Mat3,
+}
+
+fn outer(a: Vec3, b: Vec3) -> Mat3 {
+  Mat3::from_cols(a * b.x, a * b.y, a * b.z)
+}
+
+// The inertia tensor of a body with the given covariance matrix, about the same point
+fn covariance_to_inertia(covariance: Mat3) -> Mat3 {
+  Mat3::from_value(covariance.trace()) - covariance
+}
+
+impl ConvexPolyhedron {
+  // By the divergence theorem, the hull can be split into tetrahedra, one for each triangle of the
+  // surface, which all share a corner. Using one of the vertices as that corner (instead of the origin)
+  // keeps the numbers small for hulls which are far from the origin.
+  fn moments(& self) -> Moments {
+    let reference = if self.is_empty() { Pt::origin() } else { self.vertices[0] };
+    let mut moments = Moments { reference: reference, volume: 0.0, first: Vec3::zero(), second: Mat3::zero() };
+
+    for face in self.faces.iter() {
+      let a = self.vertices[face[0]] - reference;
+      for idx in 1..(face.len() - 1) {
+        let b = self.vertices[face[idx]] - reference;
+        let c = self.vertices[face[idx + 1]] - reference;
+        let det = a.dot(b.cross(c));
+        let sum = a + b + c;
+
+        moments.volume += det / 6.0;
+        moments.first += sum * (det / 24.0);
+        // The covariance of the tetrahedron (0, a, b, c), from the canonical tetrahedron's
+        moments.second += (outer(a, a) + outer(b, b) + outer(c, c) + outer(sum, sum)) * (det / 120.0);
+      }
+    }
+
+    return moments;
+  }
+
+  // The volume enclosed by the hull
+  pub fn volume(& self) -> f32 {
+    self.moments().volume
+  }
+
+  pub fn surface_area(& self) -> f32 {
+    let mut area = 0.0;
+    for face in self.faces.iter() {
+      let first = self.vertices[face[0]];
+      for idx in 1..(face.len() - 1) {
+        area += (self.vertices[face[idx]] - first).cross(self.vertices[face[idx + 1]] - first).magnitude() / 2.0;
+      }
+    }
+    return area;
+  }
+
+  // The center of mass of the solid hull, assuming a uniform density.
+  // Returns None if the hull is empty or flat.
+  pub fn centroid(& self) -> Option<Pt> {
+    let moments = self.moments();
+    if moments.volume <= 0.0 { return None; }
+    Some(moments.reference + moments.first / moments.volume)
+  }
+
+  // The inertia tensor of the solid hull about its centroid, for a uniform density.
+  // An empty or flat hull has no inertia.
+  pub fn inertia_tensor(& self, density: f32) -> Mat3 {
+    let moments = self.moments();
+    if moments.volume <= 0.0 { return Mat3::zero(); }
+
+    // Move the covariance from the reference point to the centroid
+    let offset = moments.first / moments.volume;
+    let covariance = moments.second - outer(offset, offset) * moments.volume;
+    covariance_to_inertia(covariance) * density
+  }
+
+  // The inertia tensor of the solid hull about any point, using the parallel axis theorem
+  pub fn inertia_tensor_about(& self, pt: Pt, density: f32) -> Mat3 {
+    let centroid = match self.centroid() {
+      Some(centroid) => centroid,
+      None => return Mat3::zero(),
+    };
+    let offset = centroid - pt;
+    let mass = self.volume() * density;
+    self.inertia_tensor(density) + covariance_to_inertia(outer(offset, offset)) * mass
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  fn assert_matrix_near(found: Mat3, expected: Mat3) {
+    for col in 0..3 {
+      for row in 0..3 { assert!((found[col][row] - expected[col][row]).abs() < 1e-3, "{:?} != {:?}", found, expected); }
+    }
+  }
+
+  #[test]
+  fn cube_at_the_origin() {
+    let hull = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    assert!((hull.volume() - 8.0).abs() < 1e-4);
+    assert!((hull.surface_area() - 24.0).abs() < 1e-4);
+    assert!(hull.centroid().unwrap().to_vec().magnitude() < 1e-4);
+    // A mass of 8 with sides of 2: 8 * (2^2 + 2^2) / 12 about each axis
+    assert_matrix_near(hull.inertia_tensor(1.0), Mat3::from_value(16.0 / 3.0));
+    assert_matrix_near(hull.inertia_tensor(2.0), Mat3::from_value(32.0 / 3.0));
+  }
+
+  #[test]
+  fn moved_box_shifts_its_inertia() {
+    let center = Pt::new(3.0, -1.0, 2.0);
+    let hull = get_convex_polyhedron(box_corners(center, Vec3::new(1.0, 2.0, 0.5)));
+    assert!((hull.volume() - 8.0).abs() < 1e-3);
+    assert!((hull.surface_area() - 28.0).abs() < 1e-3);
+    assert!((hull.centroid().unwrap() - center).magnitude() < 1e-4);
+
+    // Sides of 2, 4 and 1
+    let about_centroid = Mat3::from_diagonal(Vec3::new(16.0 + 1.0, 4.0 + 1.0, 4.0 + 16.0) * (8.0 / 12.0));
+    assert_matrix_near(hull.inertia_tensor(1.0), about_centroid);
+    assert_matrix_near(hull.inertia_tensor_about(center, 1.0), about_centroid);
+
+    // The parallel axis theorem: mass * (|d|^2 I - d d^T)
+    let d = center.to_vec();
+    let shift = Mat3::from_cols(
+      Vec3::new(d.y * d.y + d.z * d.z, -d.x * d.y, -d.x * d.z),
+      Vec3::new(-d.y * d.x, d.x * d.x + d.z * d.z, -d.y * d.z),
+      Vec3::new(-d.z * d.x, -d.z * d.y, d.x * d.x + d.y * d.y));
+    assert_matrix_near(hull.inertia_tensor_about(Pt::origin(), 1.0), about_centroid + shift * 8.0);
+  }
+
+  #[test]
+  fn flat_hull_has_no_mass() {
+    let square = vec![Pt::new(0.0, 0.0, 0.0), Pt::new(1.0, 0.0, 0.0), Pt::new(1.0, 1.0, 0.0), Pt::new(0.0, 1.0, 0.0)];
+    let hull = get_convex_polyhedron(square);
+    assert_eq!(hull.volume(), 0.0);
+    assert!(hull.centroid().is_none());
+    assert_eq!(hull.inertia_tensor(1.0), Mat3::zero());
+  }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::{ConvexPolyhedron, Plane};
+use convex_hull::get_convex_polyhedron;
+use contact::is_minkowski_face;
+
+// The hull reflected through the origin
+fn negated(hull: & ConvexPolyhedron) -> ConvexPolyhedron {
+  // Reflecting turns the faces inside out, so they're reversed to stay counterclockwise
+  let faces = hull.faces.iter().map(|face| face.iter().rev().cloned().collect()).collect();
+  let mut reflected = ConvexPolyhedron::from_faces(hull.vertices.iter().map(|& v| Pt::new(-v.x, -v.y, -v.z)).collect(), faces);
+  reflected.planes = hull.planes.iter().map(|plane| Plane::new(-plane.normal, plane.offset)).collect();
+  reflected.vertex_ids = hull.vertex_ids.clone();
+  reflected.face_ids = hull.face_ids.clone();
+  return reflected;
+}
+
+// The pairs of vertices whose sums can be vertices of the Minkowski sum, found from the overlay of the
+// hulls' Gauss maps. Every region of the overlay has a corner at a face normal of one of the hulls,
+// or where an edge arc of one crosses an edge arc of the other. The pairs are each face's vertices
+// with the other hull's vertices farthest along its normal, and the ends of each pair of crossing edges.
+fn gauss_map_pairs(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> HashSet<(Idx, Idx)> {
+  let mut pairs = HashSet::new();
+
+  let tolerance_b = b.tie_tolerance();
+  for (face, plane) in a.planes.iter().enumerate() {
+    let support = b.support_set(plane.normal, tolerance_b);
+    for & v_a in a.faces[face].iter() {
+      for & v_b in support.iter() { pairs.insert((v_a, v_b)); }
+    }
+  }
+
+  let tolerance_a = a.tie_tolerance();
+  for (face, plane) in b.planes.iter().enumerate() {
+    let support = a.support_set(plane.normal, tolerance_a);
+    for & v_b in b.faces[face].iter() {
+      for & v_a in support.iter() { pairs.insert((v_a, v_b)); }
+    }
+  }
+
+  for (edge_a, faces_a) in a.edge_faces.iter().enumerate() {
+    let (normal_a1, normal_a2) = (a.planes[faces_a[0]].normal, a.planes[faces_a[1]].normal);
+    for (edge_b, faces_b) in b.edge_faces.iter().enumerate() {
+      if !is_minkowski_face(normal_a1, normal_a2, b.planes[faces_b[0]].normal, b.planes[faces_b[1]].normal) { continue; }
+      for & v_a in a.edges[edge_a].iter() {
+        for & v_b in b.edges[edge_b].iter() { pairs.insert((v_a, v_b)); }
+      }
+    }
+  }
+
+  return pairs;
+}
+
+// The Minkowski sum of two hulls: every point of one added to every point of the other.
+// This is a pruned brute force. Instead of adding every pair of vertices, the overlay of the hulls' Gauss
+// maps picks out the pairs which can make a vertex of the sum, and only those sums are hulled with
+// get_convex_polyhedron. No faces are built from the overlay itself, so it still costs a full hull build
+// of up to a few times (faces + edge crossings) points, and the sum's faces and ids don't refer back to
+// the faces of the hulls they came from. Flat hulls don't have a proper Gauss map, so for them every
+// pair of vertices is added instead. The sum is empty if either hull is empty.
+pub fn minkowski_sum(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> ConvexPolyhedron {
+  if a.is_empty() || b.is_empty() { return ConvexPolyhedron::empty(); }
+
+  let sums: Vec<Pt> = if a.volume() > 0.0 && b.volume() > 0.0 {
+    gauss_map_pairs(a, b).into_iter().map(|(v_a, v_b)| a.vertices[v_a] + b.vertices[v_b].to_vec()).collect()
+  } else {
+    a.vertices.iter().flat_map(|& v_a| b.vertices.iter().map(move |& v_b| v_a + v_b.to_vec())).collect()
+  };
+
+  get_convex_polyhedron(sums)
+}
+
+// The Minkowski difference of two hulls: every point of the second subtracted from every point of the first.
+// It contains the origin exactly when the hulls overlap, and its distance from the origin is the distance
+// between the hulls.
+pub fn minkowski_difference(a: & ConvexPolyhedron, b: & ConvexPolyhedron) -> ConvexPolyhedron {
+  minkowski_sum(a, & negated(b))
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::Rad;
+
+  use defs::*;
+  use test_support::*;
+  use super::*;
+
+  #[test]
+  fn sum_of_boxes() {
+    let a = get_convex_polyhedron(box_corners(Pt::new(1.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    let b = get_convex_polyhedron(box_corners(Pt::new(0.0, 2.0, 0.0), Vec3::new(0.5, 1.0, 1.5)));
+    let sum = minkowski_sum(& a, & b);
+    assert_eq!(sum.faces.len(), 6);
+    assert!((sum.volume() - 3.0 * 4.0 * 5.0).abs() < 1e-3);
+    assert!((sum.support_point(Vec3::new(1.0, 1.0, 1.0)) - Pt::new(2.5, 4.0, 2.5)).magnitude() < 1e-5);
+
+    // The difference is centered on the offset between them
+    let difference = minkowski_difference(& a, & b);
+    assert!((difference.volume() - 60.0).abs() < 1e-3);
+    assert!((difference.support_point(Vec3::new(1.0, 1.0, 1.0)) - Pt::new(2.5, 0.0, 2.5)).magnitude() < 1e-5);
+  }
+
+  #[test]
+  fn sum_matches_all_pairs() {
+    // Turned boxes have edges crossing on the Gauss map, which the faces alone don't account for
+    let turn = Quat::from_axis_angle(Vec3::new(1.0, 2.0, 3.0).normalize(), Rad(0.7));
+    let a = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.5, 2.0)));
+    let b = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(0.3, 1.0, 0.7)).into_iter()
+      .map(|pt| Pt::from_vec(turn.rotate_vector(pt.to_vec()))).collect::<Vec<Pt>>());
+
+    let all_pairs = get_convex_polyhedron(a.vertices.iter()
+      .flat_map(|& v_a| b.vertices.iter().map(move |& v_b| v_a + v_b.to_vec())).collect::<Vec<Pt>>());
+    let sum = minkowski_sum(& a, & b);
+    assert!((sum.volume() - all_pairs.volume()).abs() < 1e-3 * all_pairs.volume());
+    assert_eq!(sum.vertices.len(), all_pairs.vertices.len());
+  }
+}
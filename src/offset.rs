@@ -0,0 +1,167 @@
+use std::f32;
+
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::{ConvexPolyhedron, Plane};
+use convex_hull::get_convex_polyhedron;
+use minkowski::minkowski_sum;
+
+impl ConvexPolyhedron {
+  // Moves every face plane out by the distance, and intersects them again to make a new hull. A negative
+  // distance moves the planes in, which shrinks the hull; faces which shrink away are left out, and the
+  // result is empty if the whole hull does. Each face keeps the id of the face it was moved from, while
+  // the vertices are numbered in order. Moving the planes in is the same as rounded shrinking, but
+  // growing this way makes sharp corners stick out farther than the distance.
+  pub fn offset(& self, distance: f32) -> ConvexPolyhedron {
+    if self.is_empty() { return ConvexPolyhedron::empty(); }
+    if distance < 0.0 {
+      self.clipped_by_moved_planes(self.clone(), distance)
+    } else {
+      match self.grown_bounds(distance) {
+        Some(bounds) => self.clipped_by_moved_planes(bounds, distance),
+        None => ConvexPolyhedron::empty(),
+      }
+    }
+  }
+
+  // Clips a hull containing the result by each of the planes moved out by the distance. The new faces
+  // take the ids of the faces whose planes made them.
+  fn clipped_by_moved_planes(& self, start: ConvexPolyhedron, distance: f32) -> ConvexPolyhedron {
+    let mut clipped = start;
+    for (face, plane) in self.planes.iter().enumerate() {
+      let moved = Plane::new(plane.normal, plane.offset + distance);
+      clipped = match clipped.split_by_plane_with_id(& moved, self.face_ids[face]).0 {
+        Some(behind) => behind,
+        None => return ConvexPolyhedron::empty(),
+      };
+    }
+    clipped.vertex_ids = (0..clipped.vertices.len() as u32).collect();
+    return clipped;
+  }
+
+  // A box around the hull grown by the distance. Sharp corners stick out farther than the distance, so the
+  // vertex bounds are first scaled around the middle of the hull: if a ball of radius r around the middle
+  // fits inside, scaling by (r + distance) / r moves every plane out by at least the distance. They're then
+  // padded by the distance, so every moved plane cuts the box and gives the new face its id.
+  // Returns None if the hull is flat, with no room for a ball.
+  fn grown_bounds(& self, distance: f32) -> Option<ConvexPolyhedron> {
+    let center = Pt::from_vec(self.vertices.iter().fold(Vec3::zero(), |sum, v| sum + v.to_vec()) / self.vertices.len() as f32);
+    let radius = self.planes.iter().map(|plane| -plane.signed_distance(center)).fold(f32::MAX, f32::min);
+    let tolerance = self.tie_tolerance();
+    if radius <= tolerance { return None; }
+
+    let mut min = self.vertices[0];
+    let mut max = self.vertices[0];
+    for vert in self.vertices.iter() {
+      min = Pt::new(min.x.min(vert.x), min.y.min(vert.y), min.z.min(vert.z));
+      max = Pt::new(max.x.max(vert.x), max.y.max(vert.y), max.z.max(vert.z));
+    }
+    let scale = (radius + distance.abs()) / radius;
+    let margin = Vec3::new(1.0, 1.0, 1.0) * (distance.abs() + tolerance);
+    let (min, max) = (center + (min - center) * scale - margin, center + (max - center) * scale + margin);
+
+    let mut box_corners = Vec::with_capacity(8);
+    for & x in [min.x, max.x].iter() {
+      for & y in [min.y, max.y].iter() {
+        for & z in [min.z, max.z].iter() { box_corners.push(Pt::new(x, y, z)); }
+      }
+    }
+    Some(get_convex_polyhedron(box_corners))
+  }
+
+  // Approximates the hull grown by a rounded margin: every point within the distance of the hull. The faces
+  // move out, and are joined by rounded strips around each edge and rounded corners around each vertex. It's
+  // the Minkowski sum of the hull with a ball, with the ball replaced by a polyhedron around it which is split
+  // into the given number of segments per quarter turn. Since the polyhedron contains the ball, the result
+  // always contains the rounded hull, so it can be used as a conservative bound. Its corners stick out past
+  // the rounded hull by a few percent of the distance at most: 4% with 4 segments, and 1% with 8. A distance
+  // which isn't positive gives the same hull as offset, since shrinking has no rounded corners.
+  pub fn rounded_offset(& self, distance: f32, segments: usize) -> ConvexPolyhedron {
+    if self.is_empty() || distance <= 0.0 { return self.offset(distance); }
+    minkowski_sum(self, & ball_around(distance, segments))
+  }
+}
+
+// A polyhedron around the ball of the given radius, centered on the origin. Points are spread around the
+// unit sphere by rings, about a segment apart, and their hull is scaled up until its closest face is at the
+// radius, so that the whole ball is inside of it.
+fn ball_around(radius: f32, segments: usize) -> ConvexPolyhedron {
+  let step = f32::consts::FRAC_PI_2 / segments.max(1) as f32;
+  let rings = 2 * segments.max(1);
+  let mut directions: Vec<Vec3> = Vec::new();
+  for ring in 0..(rings + 1) {
+    let polar = ring as f32 * step;
+    let around = ((2.0 * f32::consts::PI * polar.sin()) / step).ceil().max(1.0) as usize;
+    for idx in 0..around {
+      let azimuth = 2.0 * f32::consts::PI * idx as f32 / around as f32;
+      directions.push(Vec3::new(polar.sin() * azimuth.cos(), polar.sin() * azimuth.sin(), polar.cos()));
+    }
+  }
+
+  let unit = get_convex_polyhedron(directions.iter().map(|& direction| Pt::from_vec(direction)).collect::<Vec<Pt>>());
+  let closest = unit.planes.iter().fold(1.0, |closest: f32, plane| closest.min(plane.offset));
+  get_convex_polyhedron(directions.into_iter().map(|direction| Pt::from_vec(direction * (radius / closest))).collect::<Vec<Pt>>())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::f32;
+
+  use test_support::*;
+  use super::*;
+
+  #[test]
+  fn planar_offsets_of_a_cube() {
+    let grown = cube_hull().offset(0.5);
+    assert_eq!(grown.faces.len(), 6);
+    assert!((grown.volume() - 27.0).abs() < 1e-3);
+    // Every face comes from moving one of the cube's
+    let (mut grown_ids, mut cube_ids) = (grown.face_ids.clone(), cube_hull().face_ids);
+    grown_ids.sort();
+    cube_ids.sort();
+    assert_eq!(grown_ids, cube_ids);
+
+    let shrunk = cube_hull().offset(-0.5);
+    assert!((shrunk.volume() - 1.0).abs() < 1e-3);
+    assert!(cube_hull().offset(-1.5).is_empty());
+  }
+
+  #[test]
+  fn sharp_corners_stick_out() {
+    let spike = get_convex_polyhedron(vec![Pt::new(-1.0, -1.0, 0.0), Pt::new(1.0, -1.0, 0.0), Pt::new(0.0, 1.0, 0.0), Pt::new(0.0, 0.0, 10.0)]);
+    let grown = spike.offset(0.5);
+    assert_eq!(grown.vertices.len(), 4);
+    assert_eq!(grown.faces.len(), 4);
+    for plane in grown.planes.iter() {
+      assert!(spike.planes.iter().any(|p| (p.normal - plane.normal).magnitude() < 1e-5 && (p.offset + 0.5 - plane.offset).abs() < 1e-4));
+    }
+
+    // The tip is where the three side planes meet once they're moved out
+    let sides: Vec<Plane> = spike.planes.iter().filter(|plane| plane.normal.z > 0.0).cloned().collect();
+    let normals = Mat3::from_cols(sides[0].normal, sides[1].normal, sides[2].normal).transpose();
+    let tip = Pt::from_vec(normals.invert().unwrap() * Vec3::new(sides[0].offset + 0.5, sides[1].offset + 0.5, sides[2].offset + 0.5));
+    assert!(tip.z > 11.0);
+    assert!(grown.vertices.iter().any(|& pt| (pt - tip).magnitude() < 1e-3));
+  }
+
+  #[test]
+  fn rounded_offset_contains_the_rounded_cube() {
+    // The cube, its faces moved out, a quarter cylinder along each edge and an eighth of a ball at each corner
+    let radius = 0.5;
+    let exact = 8.0 + 24.0 * radius + 6.0 * f32::consts::PI * radius * radius + 4.0 / 3.0 * f32::consts::PI * radius * radius * radius;
+    for & (segments, farthest) in [(2, 1.2), (4, 1.05), (8, 1.02)].iter() {
+      let rounded = cube_hull().rounded_offset(radius, segments);
+      assert!(rounded.volume() >= exact - 1e-3, "{} segments", segments);
+
+      // Every face is at least the radius from the cube, and no corner is much farther
+      for plane in rounded.planes.iter() {
+        assert!(plane.offset - plane.normal.dot(cube_hull().support_point(plane.normal).to_vec()) >= radius - 1e-4);
+      }
+      for & pt in rounded.vertices.iter() {
+        assert!((cube_hull().closest_point(pt).unwrap().point - pt).magnitude() <= radius * farthest, "{} segments", segments);
+      }
+    }
+    assert!(cube_hull().rounded_offset(radius, 8).volume() < exact * 1.01);
+  }
+}
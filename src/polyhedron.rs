@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+
+use cgmath::prelude::*;
+
+use defs::*;
+use convex_hull::chain_edges;
+use half_edge_mesh::{HalfEdgeMesh, ToPtrVec};
+
+// Adjacent hull triangles are merged into one face when they're within this distance of
+// each other's planes, relative to the size of the hull
+const COPLANAR_EPSILON: f32 = 1e-6;
+
+// A plane, stored as a unit normal and the plane's offset from the origin along the normal.
+// Points in the direction of the normal are in front of the plane, at a positive distance.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plane {
+  pub normal: Vec3,
+  pub offset: f32,
+}
+
+impl Plane {
+  pub fn new(normal: Vec3, offset: f32) -> Plane {
+    Plane { normal: normal, offset: offset }
+  }
+
+  pub fn from_point_normal(pt: Pt, normal: Vec3) -> Plane {
+    let unit_normal = normal.normalize();
+    Plane { normal: unit_normal, offset: unit_normal.dot(pt.to_vec()) }
+  }
+
+  // The plane through three points, facing the side from which they're counterclockwise
+  pub fn from_points(pt1: Pt, pt2: Pt, pt3: Pt) -> Plane {
+    Plane::from_point_normal(pt1, (pt2 - pt1).cross(pt3 - pt1))
+  }
+
+  pub fn signed_distance(& self, pt: Pt) -> f32 {
+    self.normal.dot(pt.to_vec()) - self.offset
+  }
+
+  pub fn project(& self, pt: Pt) -> Pt {
+    pt - self.normal * self.signed_distance(pt)
+  }
+
+  pub fn flipped(& self) -> Plane {
+    Plane { normal: -self.normal, offset: -self.offset }
+  }
+}
+
+// A compact representation of a convex polyhedron, using flat arrays and indexes instead of
+// the reference counted elements of a HalfEdgeMesh, so it's cheap to copy around and query.
+// Coplanar triangles of the hull are merged, so faces are convex polygons.
+#[derive(Clone, Debug)]
+pub struct ConvexPolyhedron {
+  pub vertices: Vec<Pt>,
+  // The plane of each face, facing outwards
+  pub planes: Vec<Plane>,
+  // The vertex indexes of each face, counterclockwise when seen from outside
+  pub faces: Vec<Vec<Idx>>,
+  // Each edge appears once, as a pair of vertex indexes
+  pub edges: Vec<[Idx; 2]>,
+  // The faces on either side of each edge. The edge runs counterclockwise around the first one
+  pub edge_faces: Vec<[Idx; 2]>,
+  // The edges around each face, where face_edges[f][i] goes from faces[f][i] to the next vertex
+  pub face_edges: Vec<Vec<Idx>>,
+  // The vertices connected to each vertex by an edge
+  pub vertex_neighbors: Vec<Vec<Idx>>,
+  // The ids of the vertices and faces in the mesh this was made from. Faces which were merged
+  // from several triangles have the smallest of their ids
+  pub vertex_ids: Vec<u32>,
+  pub face_ids: Vec<u32>,
+}
+
+impl ConvexPolyhedron {
+  pub fn empty() -> ConvexPolyhedron {
+    ConvexPolyhedron {
+      vertices: Vec::new(),
+      planes: Vec::new(),
+      faces: Vec::new(),
+      edges: Vec::new(),
+      edge_faces: Vec::new(),
+      face_edges: Vec::new(),
+      vertex_neighbors: Vec::new(),
+      vertex_ids: Vec::new(),
+      face_ids: Vec::new(),
+    }
+  }
+
+  pub fn is_empty(& self) -> bool {
+    self.faces.is_empty()
+  }
+
+  // Builds a polyhedron from vertices and face loops (counterclockwise seen from outside).
+  // Computes the face planes and the edges, and numbers the vertices and faces in order.
+  pub fn from_faces(vertices: Vec<Pt>, faces: Vec<Vec<Idx>>) -> ConvexPolyhedron {
+    let planes = faces.iter().map(|face| polygon_plane(& vertices, face)).collect();
+
+    // Each edge is seen from both of its faces. It's added by the first one, and the
+    // second one finds it by looking up the edge going in the opposite direction
+    let mut directed_edges: HashMap<(Idx, Idx), Idx> = HashMap::new();
+    let mut edges: Vec<[Idx; 2]> = Vec::new();
+    let mut edge_faces: Vec<[Idx; 2]> = Vec::new();
+    let mut face_edges: Vec<Vec<Idx>> = Vec::with_capacity(faces.len());
+    for (f_idx, face) in faces.iter().enumerate() {
+      let mut sides = Vec::with_capacity(face.len());
+      for (idx, & from) in face.iter().enumerate() {
+        let to = face[(idx + 1) % face.len()];
+        let e_idx = match directed_edges.get(& (to, from)) {
+          Some(& e_idx) => {
+            edge_faces[e_idx][1] = f_idx;
+            e_idx
+          },
+          // Until the other side shows up, the edge is on an open boundary
+          None => {
+            edges.push([from, to]);
+            edge_faces.push([f_idx, f_idx]);
+            edges.len() - 1
+          },
+        };
+        directed_edges.insert((from, to), e_idx);
+        sides.push(e_idx);
+      }
+      face_edges.push(sides);
+    }
+
+    let mut vertex_neighbors = vec![Vec::new(); vertices.len()];
+    for edge in edges.iter() {
+      vertex_neighbors[edge[0]].push(edge[1]);
+      vertex_neighbors[edge[1]].push(edge[0]);
+    }
+
+    ConvexPolyhedron {
+      vertex_ids: (0..vertices.len() as u32).collect(),
+      face_ids: (0..faces.len() as u32).collect(),
+      vertices: vertices,
+      planes: planes,
+      faces: faces,
+      edges: edges,
+      edge_faces: edge_faces,
+      face_edges: face_edges,
+      vertex_neighbors: vertex_neighbors,
+    }
+  }
+
+  // Converts a hull mesh, merging adjacent coplanar triangles into single faces
+  pub fn from_half_edge_mesh(mesh: & HalfEdgeMesh) -> ConvexPolyhedron {
+    if mesh.faces.is_empty() { return ConvexPolyhedron::empty(); }
+
+    let mut face_ids: Vec<u32> = mesh.faces.keys().cloned().collect();
+    face_ids.sort();
+
+    let mut triangles: Vec<Vec<u32>> = Vec::with_capacity(face_ids.len());
+    let mut normals: Vec<Vec3> = Vec::with_capacity(face_ids.len());
+    let mut positions: HashMap<u32, Pt> = HashMap::new();
+    for id in face_ids.iter() {
+      let face = & mesh.faces[id];
+      face.borrow_mut().compute_attrs();
+      normals.push(face.borrow().normal);
+      let verts = face.borrow().adjacent_verts().to_ptr_vec();
+      for vert in verts.iter() { positions.insert(vert.borrow().id, vert.borrow().pos); }
+      triangles.push(verts.iter().map(|v| v.borrow().id).collect());
+    }
+
+    // Only the vertices which are part of a face are kept
+    let mut vertex_ids: Vec<u32> = positions.keys().cloned().collect();
+    vertex_ids.sort();
+    let vertices: Vec<Pt> = vertex_ids.iter().map(|id| positions[id]).collect();
+
+    let tolerance = scaled_tolerance(COPLANAR_EPSILON, positions.values().cloned());
+
+    // Group the coplanar triangles by walking across the edges between them
+    let mut directed_tris: HashMap<(u32, u32), usize> = HashMap::new();
+    for (t_idx, tri) in triangles.iter().enumerate() {
+      for (idx, & vert) in tri.iter().enumerate() {
+        directed_tris.insert((vert, tri[(idx + 1) % tri.len()]), t_idx);
+      }
+    }
+
+    let mut group_of: Vec<Option<usize>> = vec![None; triangles.len()];
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for start in 0..triangles.len() {
+      if group_of[start].is_some() { continue; }
+      let group_idx = groups.len();
+      let mut group = vec![start];
+      group_of[start] = Some(group_idx);
+      let plane = Plane::from_point_normal(positions[& triangles[start][0]], normals[start]);
+
+      let mut pending = vec![start];
+      while let Some(t_idx) = pending.pop() {
+        let tri = & triangles[t_idx];
+        for (idx, & vert) in tri.iter().enumerate() {
+          let neighbor = match directed_tris.get(& (tri[(idx + 1) % tri.len()], vert)) {
+            Some(& n) => n,
+            None => continue,
+          };
+          let is_coplanar = normals[neighbor].dot(plane.normal) > 0.0 &&
+            triangles[neighbor].iter().all(|id| plane.signed_distance(positions[id]).abs() < tolerance);
+          if group_of[neighbor].is_none() && is_coplanar {
+            group_of[neighbor] = Some(group_idx);
+            group.push(neighbor);
+            pending.push(neighbor);
+          }
+        }
+      }
+
+      groups.push(group);
+    }
+
+    // The outline of each group of triangles is one face
+    let vertex_index: HashMap<u32, Idx> = vertex_ids.iter().enumerate().map(|(idx, & id)| (id, idx)).collect();
+    let mut faces: Vec<Vec<Idx>> = Vec::with_capacity(groups.len());
+    let mut group_face_ids: Vec<u32> = Vec::with_capacity(groups.len());
+    for group in groups.iter() {
+      let mut outline: HashMap<u32, u32> = HashMap::new();
+      for & t_idx in group.iter() {
+        let tri = & triangles[t_idx];
+        for (idx, & vert) in tri.iter().enumerate() {
+          let next = tri[(idx + 1) % tri.len()];
+          let is_outline = match directed_tris.get(& (next, vert)) {
+            Some(& neighbor) => group_of[neighbor] != group_of[t_idx],
+            None => true,
+          };
+          if is_outline { outline.insert(vert, next); }
+        }
+      }
+      faces.push(chain_edges(outline).into_iter().map(|(from, _)| vertex_index[& from]).collect());
+      group_face_ids.push(group.iter().map(|& t_idx| face_ids[t_idx]).min().unwrap());
+    }
+
+    let mut polyhedron = ConvexPolyhedron::from_faces(vertices, faces);
+    polyhedron.vertex_ids = vertex_ids;
+    polyhedron.face_ids = group_face_ids;
+    return polyhedron;
+  }
+
+  // The face on the other side of an edge of the given face
+  pub fn across_edge(& self, face: Idx, edge: Idx) -> Idx {
+    let sides = self.edge_faces[edge];
+    if sides[0] == face { sides[1] } else { sides[0] }
+  }
+
+  // The faces around each vertex, counterclockwise when seen from outside
+  pub fn vertex_faces(& self) -> Vec<Vec<Idx>> {
+    let mut face_after: Vec<HashMap<u32, u32>> = vec![HashMap::new(); self.vertices.len()];
+    for (face, verts) in self.faces.iter().enumerate() {
+      for (idx, & v) in verts.iter().enumerate() {
+        // Going counterclockwise, each face is followed by the one across its edge coming into the vertex
+        let incoming = self.face_edges[face][(idx + verts.len() - 1) % verts.len()];
+        face_after[v].insert(face as u32, self.across_edge(face, incoming) as u32);
+      }
+    }
+    face_after.into_iter()
+      .map(|after| chain_edges(after).into_iter().map(|(face, _)| face as Idx).collect())
+      .collect()
+  }
+
+  pub fn face_points(& self, face: Idx) -> Vec<Pt> {
+    self.faces[face].iter().map(|& v| self.vertices[v]).collect()
+  }
+}
+
+// Computes the plane of a polygon using Newell's method, which averages out any unevenness
+fn polygon_plane(vertices: & [Pt], polygon: & [Idx]) -> Plane {
+  let mut normal = Vec3::zero();
+  let mut center = Vec3::zero();
+  for (idx, & v) in polygon.iter().enumerate() {
+    let current = vertices[v];
+    let next = vertices[polygon[(idx + 1) % polygon.len()]];
+    normal.x += (current.y - next.y) * (current.z + next.z);
+    normal.y += (current.z - next.z) * (current.x + next.x);
+    normal.z += (current.x - next.x) * (current.y + next.y);
+    center += current.to_vec();
+  }
+  center /= polygon.len() as f32;
+  Plane::from_point_normal(Pt::from_vec(center), normal)
+}
+
+#[cfg(test)]
+mod tests {
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  // Checks that the edges, the faces around them and the faces around each vertex all agree
+  fn assert_adjacency(hull: & ConvexPolyhedron) {
+    for (face, verts) in hull.faces.iter().enumerate() {
+      assert_eq!(hull.face_edges[face].len(), verts.len());
+      for (idx, & edge) in hull.face_edges[face].iter().enumerate() {
+        let (from, to) = (verts[idx], verts[(idx + 1) % verts.len()]);
+        let ends = hull.edges[edge];
+        assert!(ends == [from, to] || ends == [to, from]);
+        assert!(hull.edge_faces[edge].contains(& face));
+      }
+    }
+
+    for (edge, & [from, to]) in hull.edges.iter().enumerate() {
+      let [first, second] = hull.edge_faces[edge];
+      assert!(first != second);
+      assert!(hull.face_edges[second].contains(& edge));
+      // The edge runs counterclockwise around its first face
+      let verts = & hull.faces[first];
+      let at = verts.iter().position(|& v| v == from).unwrap();
+      assert_eq!(verts[(at + 1) % verts.len()], to);
+    }
+
+    for (vert, around) in hull.vertex_faces().iter().enumerate() {
+      assert_eq!(around.len(), hull.vertex_neighbors[vert].len());
+      for (idx, & face) in around.iter().enumerate() {
+        assert!(hull.faces[face].contains(& vert));
+        // Faces next to each other around the vertex share one of its edges
+        let next = around[(idx + 1) % around.len()];
+        assert!(hull.face_edges[face].iter().any(|& e| hull.edges[e].contains(& vert) && hull.edge_faces[e].contains(& next)));
+      }
+    }
+  }
+
+  #[test]
+  fn box_triangles_merge_into_quads() {
+    let hull = get_convex_polyhedron(box_corners(Pt::new(3.0, -1.0, 2.0), Vec3::new(1.0, 2.0, 0.5)));
+    assert_eq!(hull.vertices.len(), 8);
+    assert_eq!(hull.faces.len(), 6);
+    assert_eq!(hull.edges.len(), 12);
+    assert!(hull.faces.iter().all(|face| face.len() == 4));
+    assert!(hull.vertex_neighbors.iter().all(|neighbors| neighbors.len() == 3));
+    assert!(hull.vertex_faces().iter().all(|around| around.len() == 3));
+
+    // Every face is on one side of the box, facing away from its center
+    for (face, plane) in hull.planes.iter().enumerate() {
+      let axis = [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()].iter()
+        .map(|axis| plane.normal.dot(* axis).abs()).fold(0.0, f32::max);
+      assert!((axis - 1.0).abs() < 1e-6);
+      assert!(plane.signed_distance(Pt::new(3.0, -1.0, 2.0)) < 0.0);
+      assert!(hull.face_points(face).iter().all(|& pt| plane.signed_distance(pt).abs() < 1e-5));
+    }
+    assert_adjacency(& hull);
+  }
+
+  #[test]
+  fn adjacency_of_a_random_hull() {
+    let hull = get_convex_polyhedron(ball_points(200, 13));
+    // Euler's formula holds for any closed convex polyhedron
+    assert_eq!(hull.vertices.len() + hull.faces.len(), hull.edges.len() + 2);
+    assert_adjacency(& hull);
+  }
+
+  #[test]
+  fn from_faces_numbers_everything_in_order() {
+    let vertices = vec![Pt::new(0.0, 0.0, 0.0), Pt::new(1.0, 0.0, 0.0), Pt::new(0.0, 1.0, 0.0), Pt::new(0.0, 0.0, 1.0)];
+    let faces = vec![vec![0, 2, 1], vec![0, 1, 3], vec![0, 3, 2], vec![1, 2, 3]];
+    let hull = ConvexPolyhedron::from_faces(vertices, faces);
+    assert_eq!(hull.edges.len(), 6);
+    assert_eq!(hull.vertex_ids, vec![0, 1, 2, 3]);
+    assert_eq!(hull.face_ids, vec![0, 1, 2, 3]);
+    assert!((hull.planes[0].normal + Vec3::unit_z()).magnitude() < 1e-6);
+    assert!((hull.planes[3].offset - 1.0 / 3.0f32.sqrt()).abs() < 1e-6);
+    assert_adjacency(& hull);
+  }
+}
@@ -0,0 +1,504 @@
+use std::f32;
+
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::ConvexPolyhedron;
+
+// A ray counts as parallel to a face when the cosine of its angle with the face's normal is
+// at most this, and as behind the face's plane when it's within this distance of it, relative
+// to the size of the hull
+const PARALLEL_EPSILON: f32 = 1e-6;
+
+// Where a point is relative to a hull, see ConvexPolyhedron::contains
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Containment {
+  Inside,
+  OnBoundary,
+  Outside,
+}
+
+// A part of a hull's surface, identified by its index in the hull
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Feature {
+  Vertex(Idx),
+  Edge(Idx),
+  Face(Idx),
+}
+
+// The result of ConvexPolyhedron::closest_point. The distance is negative when the
+// query point is inside the hull
+#[derive(Copy, Clone, Debug)]
+pub struct ClosestPoint {
+  pub point: Pt,
+  pub feature: Feature,
+  pub distance: f32,
+}
+
+// The result of casting a ray against a hull. The ray is origin + direction * t.
+// t_enter and t_exit are where the line of the ray enters and leaves the hull, t_enter is negative
+// when the ray starts inside. The hit point is where the ray first crosses the surface, which is
+// the exit point for rays that start inside, and face and normal belong to the face it crosses.
+#[derive(Copy, Clone, Debug)]
+pub struct RayHit {
+  pub t_enter: f32,
+  pub t_exit: f32,
+  pub t: f32,
+  pub point: Pt,
+  pub face: Idx,
+  pub normal: Vec3,
+}
+
+pub fn closest_point_on_segment(pt1: Pt, pt2: Pt, target: Pt) -> Pt {
+  let line = pt2 - pt1;
+  let line_length2 = line.magnitude2();
+  if line_length2 == 0.0 { return pt1; }
+  let t = ((target - pt1).dot(line) / line_length2).max(0.0).min(1.0);
+  pt1 + line * t
+}
+
+// The closest points between two segments, the first from start_a to end_a and the second from start_b
+// to end_b, given in that order. Solved directly, from Christer Ericson's "Real-Time Collision Detection"
+// (5.1.9): the closest points of the infinite lines are clamped to the first segment, and then each
+// parameter is clamped again against the other. Parallel segments take the point closest to start_a.
+pub fn closest_points_on_segments(start_a: Pt, end_a: Pt, start_b: Pt, end_b: Pt) -> (Pt, Pt) {
+  let clamp = |t: f32| t.max(0.0).min(1.0);
+  let (dir_a, dir_b, between) = (end_a - start_a, end_b - start_b, start_a - start_b);
+  let (len2_a, len2_b, f) = (dir_a.magnitude2(), dir_b.magnitude2(), dir_b.dot(between));
+
+  let (s, t) = if len2_a == 0.0 && len2_b == 0.0 {
+    (0.0, 0.0)
+  } else if len2_a == 0.0 {
+    (0.0, clamp(f / len2_b))
+  } else {
+    let c = dir_a.dot(between);
+    if len2_b == 0.0 {
+      (clamp(-c / len2_a), 0.0)
+    } else {
+      let b = dir_a.dot(dir_b);
+      let denom = len2_a * len2_b - b * b;
+      let s = if denom > 0.0 { clamp((b * f - c * len2_b) / denom) } else { 0.0 };
+      let t = (b * s + f) / len2_b;
+      if t < 0.0 {
+        (clamp(-c / len2_a), 0.0)
+      } else if t > 1.0 {
+        (clamp((b - c) / len2_a), 1.0)
+      } else {
+        (s, t)
+      }
+    }
+  };
+
+  (start_a + dir_a * s, start_b + dir_b * t)
+}
+
+impl ConvexPolyhedron {
+  // The largest signed distance from the point to any of the face planes. For a point inside
+  // the hull this is exactly the (negative) distance to the boundary, since the hull is convex.
+  fn max_plane_distance(& self, pt: Pt) -> f32 {
+    self.planes.iter().fold(f32::NEG_INFINITY, |max, plane| max.max(plane.signed_distance(pt)))
+  }
+
+  // The closest point to the target on a face, treating the face as a filled polygon,
+  // along with the part of the face (a corner, a side, or the inside) that it's on
+  pub fn closest_point_on_face(& self, face: Idx, target: Pt) -> (Pt, Feature) {
+    let plane = & self.planes[face];
+    let verts = & self.faces[face];
+    let projected = plane.project(target);
+
+    // If the projected point is inside all of the edges, it's on the face.
+    // Otherwise, it's on one of the edges that it's outside of
+    let mut closest: Option<(Pt, Feature, f32)> = None;
+    for (idx, & v) in verts.iter().enumerate() {
+      let next_v = verts[(idx + 1) % verts.len()];
+      let (pt1, pt2) = (self.vertices[v], self.vertices[next_v]);
+      if (pt2 - pt1).cross(projected - pt1).dot(plane.normal) >= 0.0 { continue; }
+
+      let on_edge = closest_point_on_segment(pt1, pt2, target);
+      let dist = (on_edge - target).magnitude2();
+      if closest.map_or(false, |(_, _, closest_dist)| closest_dist <= dist) { continue; }
+
+      let feature = if on_edge == pt1 {
+        Feature::Vertex(v)
+      } else if on_edge == pt2 {
+        Feature::Vertex(next_v)
+      } else {
+        Feature::Edge(self.face_edges[face][idx])
+      };
+      closest = Some((on_edge, feature, dist));
+    }
+
+    match closest {
+      Some((pt, feature, _)) => (pt, feature),
+      None => (projected, Feature::Face(face)),
+    }
+  }
+
+  // Walks across the faces from the start towards the point, moving to whichever neighbor is most
+  // in front of it, until reaching a face which can see it. Returns None if the walk gets stuck.
+  fn walk_to_visible_face(& self, start: Idx, pt: Pt) -> Option<Idx> {
+    let mut current = start;
+    let mut current_dist = self.planes[current].signed_distance(pt);
+    while current_dist <= 0.0 {
+      let (best, best_dist) = self.face_edges[current].iter()
+        .map(|& edge| self.across_edge(current, edge))
+        .map(|face| (face, self.planes[face].signed_distance(pt)))
+        .fold((current, current_dist), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+      if best == current { return None; }
+      current = best;
+      current_dist = best_dist;
+    }
+    Some(current)
+  }
+
+  // Finds the closest point on the surface of the hull, and the vertex, edge or face it's on.
+  // For a point inside the hull, this is the nearest point on the boundary.
+  // Returns None if the hull is empty.
+  pub fn closest_point(& self, pt: Pt) -> Option<ClosestPoint> {
+    self.closest_point_from(pt, 0).map(|(closest, _)| closest)
+  }
+
+  // Finds the closest point, starting the walk towards the point from the given face.
+  // Also returns the face the walk ended at, which is a good start for a point nearby.
+  fn closest_point_from(& self, pt: Pt, start: Idx) -> Option<(ClosestPoint, Idx)> {
+    if self.is_empty() { return None; }
+
+    let mut candidate_faces: Vec<Idx> = Vec::new();
+    let mut end = start;
+    match self.walk_to_visible_face(start, pt) {
+      Some(visible) => {
+        // The faces which can see the point form a connected patch, and the closest
+        // point is somewhere in that patch. Flood fill it outwards from the first one
+        end = visible;
+        let mut visited = vec![false; self.faces.len()];
+        visited[visible] = true;
+        let mut pending = vec![visible];
+        while let Some(face) = pending.pop() {
+          candidate_faces.push(face);
+          for & edge in self.face_edges[face].iter() {
+            let neighbor = self.across_edge(face, edge);
+            if !visited[neighbor] && self.planes[neighbor].signed_distance(pt) > 0.0 {
+              visited[neighbor] = true;
+              pending.push(neighbor);
+            }
+          }
+        }
+      },
+      None => {
+        let (nearest_face, plane_dist) = (0..self.planes.len())
+          .map(|face| (face, self.planes[face].signed_distance(pt)))
+          .fold((0, f32::NEG_INFINITY), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+        if plane_dist <= 0.0 {
+          // Inside the hull, the nearest boundary point is directly below the nearest face plane
+          let (point, feature) = self.closest_point_on_face(nearest_face, pt);
+          return Some((ClosestPoint { point: point, feature: feature, distance: plane_dist }, end));
+        }
+        candidate_faces = (0..self.faces.len()).filter(|& face| self.planes[face].signed_distance(pt) > 0.0).collect();
+      },
+    }
+
+    candidate_faces.into_iter()
+      .map(|face| {
+        let (point, feature) = self.closest_point_on_face(face, pt);
+        ClosestPoint { point: point, feature: feature, distance: (point - pt).magnitude() }
+      })
+      .fold(None, |closest: Option<ClosestPoint>, candidate| {
+        match closest {
+          Some(c) if c.distance <= candidate.distance => Some(c),
+          _ => Some(candidate),
+        }
+      })
+      .map(|closest| (closest, end))
+  }
+
+  // Clips the line through origin along direction against every face plane (like the slab method
+  // for boxes), keeping track of the faces where the line enters and leaves.
+  // Returns (t_enter, enter_face, t_exit, exit_face), or None if the line misses the hull.
+  fn clip_line(& self, origin: Pt, direction: Vec3) -> Option<(f32, Option<Idx>, f32, Option<Idx>)> {
+    let mut t_enter = f32::NEG_INFINITY;
+    let mut t_exit = f32::INFINITY;
+    let mut enter_face = None;
+    let mut exit_face = None;
+    let tolerance = scaled_tolerance(PARALLEL_EPSILON, self.vertices.iter().cloned());
+    let parallel_limit = PARALLEL_EPSILON * direction.magnitude();
+
+    for (face, plane) in self.planes.iter().enumerate() {
+      let approach = plane.normal.dot(direction);
+      let dist = plane.signed_distance(origin);
+      if approach.abs() <= parallel_limit {
+        // Parallel to the plane, the line is either always in front or always behind it.
+        // A line along the face itself counts as behind it.
+        if dist > tolerance { return None; }
+        continue;
+      }
+      let t = -dist / approach;
+      if approach < 0.0 && t > t_enter {
+        t_enter = t;
+        enter_face = Some(face);
+      } else if approach > 0.0 && t < t_exit {
+        t_exit = t;
+        exit_face = Some(face);
+      }
+      if t_enter > t_exit { return None; }
+    }
+
+    Some((t_enter, enter_face, t_exit, exit_face))
+  }
+
+  fn cast(& self, origin: Pt, direction: Vec3, t_max: f32) -> Option<RayHit> {
+    if self.is_empty() || direction.magnitude2() == 0.0 { return None; }
+    let (t_enter, enter_face, t_exit, exit_face) = self.clip_line(origin, direction)?;
+    if t_exit < 0.0 || t_enter > t_max { return None; }
+
+    let (t, face) = if t_enter >= 0.0 { (t_enter, enter_face?) } else { (t_exit, exit_face?) };
+    // A segment which starts inside the hull may end before getting out
+    if t > t_max { return None; }
+    Some(RayHit {
+      t_enter: t_enter,
+      t_exit: t_exit,
+      t: t,
+      point: origin + direction * t,
+      face: face,
+      normal: self.planes[face].normal,
+    })
+  }
+
+  // Casts a ray against the hull, returning None if it misses
+  pub fn ray_cast(& self, origin: Pt, direction: Vec3) -> Option<RayHit> {
+    self.cast(origin, direction, f32::INFINITY)
+  }
+
+  // Casts the segment from start to end against the hull, with t going from 0 at the start
+  // to 1 at the end. Returns None if the segment doesn't cross the surface of the hull,
+  // which includes segments lying entirely inside it (see contains to tell those apart).
+  pub fn segment_cast(& self, start: Pt, end: Pt) -> Option<RayHit> {
+    self.cast(start, end - start, 1.0)
+  }
+
+  // Classifies a point, checking the plane of the given face first. Also returns the face
+  // whose plane put the point outside, if it's outside.
+  fn classify_from(& self, pt: Pt, tolerance: f32, first: Idx) -> (Containment, Option<Idx>) {
+    if self.planes[first].signed_distance(pt) > tolerance { return (Containment::Outside, Some(first)); }
+    let mut max_dist = f32::NEG_INFINITY;
+    for (face, plane) in self.planes.iter().enumerate() {
+      let dist = plane.signed_distance(pt);
+      // Once the point is clearly outside one plane, there's no need to check the rest
+      if dist > tolerance { return (Containment::Outside, Some(face)); }
+      max_dist = max_dist.max(dist);
+    }
+    if max_dist >= -tolerance { (Containment::OnBoundary, None) } else { (Containment::Inside, None) }
+  }
+
+  // Classifies a point as inside the hull, outside of it, or on its boundary,
+  // which is everywhere within tolerance of the face planes
+  pub fn contains(& self, pt: Pt, tolerance: f32) -> Containment {
+    if self.is_empty() { return Containment::Outside; }
+    self.classify_from(pt, tolerance, 0).0
+  }
+
+  // The distance from the point to the boundary of the hull, negative inside the hull.
+  // An empty hull is infinitely far away.
+  pub fn signed_distance(& self, pt: Pt) -> f32 {
+    if self.is_empty() { return f32::INFINITY; }
+    let plane_dist = self.max_plane_distance(pt);
+    if plane_dist <= 0.0 { return plane_dist; }
+
+    self.closest_point(pt).map_or(f32::INFINITY, |closest| closest.distance)
+  }
+
+  // Batched version of contains. Points near each other tend to be outside of the same face,
+  // so the plane which put the last point outside is checked first, and usually rejects the
+  // next outside point on its own.
+  pub fn contains_all(& self, points: & [Pt], tolerance: f32) -> Vec<Containment> {
+    if self.is_empty() { return vec![Containment::Outside; points.len()]; }
+    let mut first = 0;
+    points.iter()
+      .map(|& pt| {
+        let (containment, outside_face) = self.classify_from(pt, tolerance, first);
+        first = outside_face.unwrap_or(first);
+        containment
+      })
+      .collect()
+  }
+
+  // Batched version of signed_distance. Each walk across the faces towards a point outside of
+  // the hull starts from the face the last walk ended at, so points near each other (like
+  // samples along a path or from a scan) only take a few steps each.
+  pub fn signed_distances(& self, points: & [Pt]) -> Vec<f32> {
+    if self.is_empty() { return vec![f32::INFINITY; points.len()]; }
+    let mut start = 0;
+    points.iter()
+      .map(|& pt| {
+        let plane_dist = self.max_plane_distance(pt);
+        if plane_dist <= 0.0 { return plane_dist; }
+        match self.closest_point_from(pt, start) {
+          Some((closest, face)) => {
+            start = face;
+            closest.distance
+          },
+          None => f32::INFINITY,
+        }
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  // The box from (0, 0, 0) to (2, 4, 6)
+  fn test_box() -> ConvexPolyhedron {
+    get_convex_polyhedron(box_corners(Pt::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, 3.0)))
+  }
+
+  fn assert_near(a: Pt, b: Pt) {
+    assert!((a - b).magnitude() < 1e-5, "{:?} vs {:?}", a, b);
+  }
+
+  #[test]
+  fn closest_points_between_segments() {
+    // Crossing over each other, one unit apart
+    let (on_a, on_b) = closest_points_on_segments(Pt::new(-1.0, 0.0, 0.0), Pt::new(1.0, 0.0, 0.0), Pt::new(0.5, -1.0, 1.0), Pt::new(0.5, 1.0, 1.0));
+    assert_near(on_a, Pt::new(0.5, 0.0, 0.0));
+    assert_near(on_b, Pt::new(0.5, 0.0, 1.0));
+
+    // The lines cross beyond the end of the second segment, so it's clamped and the first follows it
+    let (on_a, on_b) = closest_points_on_segments(Pt::new(-1.0, 0.0, 0.0), Pt::new(1.0, 0.0, 0.0), Pt::new(0.5, 1.0, 1.0), Pt::new(0.5, 2.0, 1.0));
+    assert_near(on_a, Pt::new(0.5, 0.0, 0.0));
+    assert_near(on_b, Pt::new(0.5, 1.0, 1.0));
+
+    // Parallel and overlapping
+    let (on_a, on_b) = closest_points_on_segments(Pt::new(0.0, 0.0, 0.0), Pt::new(2.0, 0.0, 0.0), Pt::new(1.0, 1.0, 0.0), Pt::new(3.0, 1.0, 0.0));
+    assert!(((on_a - on_b).magnitude() - 1.0).abs() < 1e-5);
+    assert_near(on_a, Pt::new(1.0, 0.0, 0.0));
+
+    // A segment which is just a point
+    let (on_a, on_b) = closest_points_on_segments(Pt::new(0.0, 3.0, 0.0), Pt::new(0.0, 3.0, 0.0), Pt::new(-1.0, 0.0, 0.0), Pt::new(1.0, 0.0, 0.0));
+    assert_near(on_a, Pt::new(0.0, 3.0, 0.0));
+    assert_near(on_b, Pt::new(0.0, 0.0, 0.0));
+  }
+
+  #[test]
+  fn containment_in_a_box() {
+    let hull = test_box();
+    assert_eq!(hull.contains(Pt::new(1.0, 1.0, 1.0), 1e-4), Containment::Inside);
+    assert_eq!(hull.contains(Pt::new(1.0, 5.0, 1.0), 1e-4), Containment::Outside);
+    // On a face, and just off of it on either side
+    assert_eq!(hull.contains(Pt::new(2.0, 1.0, 1.0), 1e-4), Containment::OnBoundary);
+    assert_eq!(hull.contains(Pt::new(2.00005, 1.0, 1.0), 1e-4), Containment::OnBoundary);
+    assert_eq!(hull.contains(Pt::new(1.99995, 1.0, 1.0), 1e-4), Containment::OnBoundary);
+    assert_eq!(hull.contains(Pt::new(2.001, 1.0, 1.0), 1e-4), Containment::Outside);
+  }
+
+  #[test]
+  fn signed_distances_to_a_box() {
+    let hull = test_box();
+    // Inside, the distance to the nearest face
+    assert!((hull.signed_distance(Pt::new(0.5, 2.0, 3.0)) + 0.5).abs() < 1e-5);
+    // Outside a face, an edge and a corner
+    assert!((hull.signed_distance(Pt::new(3.0, 2.0, 3.0)) - 1.0).abs() < 1e-5);
+    assert!((hull.signed_distance(Pt::new(3.0, 5.0, 3.0)) - 2.0_f32.sqrt()).abs() < 1e-5);
+    assert!((hull.signed_distance(Pt::new(-1.0, -2.0, -2.0)) - 3.0).abs() < 1e-5);
+    assert!(hull.signed_distance(Pt::new(2.0, 1.0, 1.0)).abs() < 1e-5);
+  }
+
+  #[test]
+  fn batches_match_single_queries() {
+    let hull = test_box();
+    let points: Vec<Pt> = ball_points(500, 7).into_iter().map(|pt| Pt::new(1.0, 2.0, 3.0) + pt.to_vec() * 5.0).collect();
+    let containment = hull.contains_all(& points, 1e-4);
+    let distances = hull.signed_distances(& points);
+    for (idx, & pt) in points.iter().enumerate() {
+      assert_eq!(containment[idx], hull.contains(pt, 1e-4));
+      assert_eq!(distances[idx], hull.signed_distance(pt));
+    }
+    assert!(containment.contains(& Containment::Inside) && containment.contains(& Containment::Outside));
+  }
+
+  #[test]
+  fn closest_features_of_a_box() {
+    let hull = test_box();
+    let vertex_at = |pt: Pt| hull.vertices.iter().position(|& v| (v - pt).magnitude() < 1e-5).unwrap();
+    let face_facing = |normal: Vec3| hull.planes.iter().position(|plane| plane.normal.dot(normal) > 0.999).unwrap();
+
+    let corner = hull.closest_point(Pt::new(3.0, 5.0, 7.0)).unwrap();
+    assert_near(corner.point, Pt::new(2.0, 4.0, 6.0));
+    assert_eq!(corner.feature, Feature::Vertex(vertex_at(Pt::new(2.0, 4.0, 6.0))));
+    assert!((corner.distance - 3.0_f32.sqrt()).abs() < 1e-5);
+
+    let side = hull.closest_point(Pt::new(3.0, 5.0, 2.0)).unwrap();
+    assert_near(side.point, Pt::new(2.0, 4.0, 2.0));
+    let (end1, end2) = (vertex_at(Pt::new(2.0, 4.0, 0.0)), vertex_at(Pt::new(2.0, 4.0, 6.0)));
+    match side.feature {
+      Feature::Edge(edge) => assert!(hull.edges[edge] == [end1, end2] || hull.edges[edge] == [end2, end1]),
+      feature => panic!("expected an edge, found {:?}", feature),
+    }
+
+    let face = hull.closest_point(Pt::new(1.5, 2.0, 8.0)).unwrap();
+    assert_near(face.point, Pt::new(1.5, 2.0, 6.0));
+    assert_eq!(face.feature, Feature::Face(face_facing(Vec3::unit_z())));
+    assert!((face.distance - 2.0).abs() < 1e-5);
+
+    // Inside, the nearest point of the boundary, at a negative distance
+    let inside = hull.closest_point(Pt::new(0.5, 2.0, 3.0)).unwrap();
+    assert_near(inside.point, Pt::new(0.0, 2.0, 3.0));
+    assert_eq!(inside.feature, Feature::Face(face_facing(-Vec3::unit_x())));
+    assert!((inside.distance + 0.5).abs() < 1e-5);
+  }
+
+  #[test]
+  fn closest_points_match_every_face() {
+    let hull = get_convex_polyhedron(ball_points(300, 8));
+    for pt in ball_points(100, 9).into_iter().map(|pt| Pt::from_vec(pt.to_vec() * 3.0)) {
+      if hull.max_plane_distance(pt) <= 0.0 { continue; }
+      let closest = hull.closest_point(pt).unwrap();
+      let brute_force = (0..hull.faces.len())
+        .map(|face| (hull.closest_point_on_face(face, pt).0 - pt).magnitude())
+        .fold(f32::INFINITY, f32::min);
+      assert!((closest.distance - brute_force).abs() < 1e-5);
+      assert!(((closest.point - pt).magnitude() - closest.distance).abs() < 1e-5);
+    }
+  }
+
+  #[test]
+  fn rays_through_a_box() {
+    let hull = test_box();
+    let hit = hull.ray_cast(Pt::new(-2.0, 1.0, 1.0), Vec3::new(2.0, 0.0, 0.0)).unwrap();
+    assert!((hit.t - 1.0).abs() < 1e-5 && (hit.t_enter - 1.0).abs() < 1e-5 && (hit.t_exit - 2.0).abs() < 1e-5);
+    assert_near(hit.point, Pt::new(0.0, 1.0, 1.0));
+    assert!((hit.normal + Vec3::unit_x()).magnitude() < 1e-5);
+    assert!((hull.planes[hit.face].normal - hit.normal).magnitude() < 1e-5);
+
+    // Pointing away, and passing beside the box
+    assert!(hull.ray_cast(Pt::new(-2.0, 1.0, 1.0), Vec3::new(-1.0, 0.0, 0.0)).is_none());
+    assert!(hull.ray_cast(Pt::new(-2.0, 1.0, 1.0), Vec3::new(0.0, 1.0, 0.0)).is_none());
+    // Parallel to the faces, just outside of one and along another
+    assert!(hull.ray_cast(Pt::new(-1.0, 1.0, 6.001), Vec3::new(1.0, 0.0, 0.0)).is_none());
+    let along = hull.ray_cast(Pt::new(-1.0, 1.0, 6.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+    assert!((along.t - 1.0).abs() < 1e-5);
+
+    // Starting inside, the hit is where the ray gets out
+    let inside = hull.ray_cast(Pt::new(1.0, 1.0, 1.0), Vec3::new(0.0, 0.0, 1.0)).unwrap();
+    assert!(inside.t_enter < 0.0);
+    assert!((inside.t - 5.0).abs() < 1e-5);
+    assert!((inside.normal - Vec3::unit_z()).magnitude() < 1e-5);
+  }
+
+  #[test]
+  fn segments_through_a_box() {
+    let hull = test_box();
+    let hit = hull.segment_cast(Pt::new(-1.0, 1.0, 1.0), Pt::new(3.0, 1.0, 1.0)).unwrap();
+    assert!((hit.t - 0.25).abs() < 1e-5);
+    // Stopping short of the box
+    assert!(hull.segment_cast(Pt::new(-1.0, 1.0, 1.0), Pt::new(-0.5, 1.0, 1.0)).is_none());
+    // Starting inside and getting out
+    let leaving = hull.segment_cast(Pt::new(1.0, 1.0, 1.0), Pt::new(1.0, 1.0, 9.0)).unwrap();
+    assert!((leaving.t - 0.625).abs() < 1e-5);
+    // Entirely inside
+    assert!(hull.segment_cast(Pt::new(0.5, 1.0, 1.0), Pt::new(1.5, 3.0, 5.0)).is_none());
+  }
+}
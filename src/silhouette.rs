@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::ConvexPolyhedron;
+use convex_hull::chain_edges;
+use bounding_box::perpendicular_basis;
+
+// Where a hull is seen from: looking along a direction (as with a directional light, or an orthographic
+// camera), or from a point (as with a point light, or a perspective camera)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum View {
+  Orthographic(Vec3),
+  Perspective(Pt),
+}
+
+// The outline of a hull as seen from a view. The edges between the front facing and back facing faces
+// make a loop, which is counterclockwise as seen from the view. Each of the loop's edges starts at the
+// vertex at the same position in the vertex loop. The outline is the vertex loop projected onto the
+// image plane, which is perpendicular to the view direction (for a perspective view, the direction from
+// the eye to the middle of the hull) and spanned by the two axes. For a perspective view, the image
+// plane is one unit in front of the eye.
+#[derive(Clone, Debug)]
+pub struct Silhouette {
+  pub front_facing: Vec<bool>,
+  pub edges: Vec<Idx>,
+  pub vertices: Vec<Idx>,
+  pub axes: [Vec3; 2],
+  pub outline: Vec<[f32; 2]>,
+}
+
+impl ConvexPolyhedron {
+  // Finds the silhouette of the hull from the view. Faces which are edge on to the view count as facing away.
+  // Returns None if the hull is empty, the direction of an orthographic view is zero (or not finite), or the
+  // eye of a perspective view is inside of the hull. A perspective
+  // view which is so close to the hull that part of it is beside or behind the eye can't be projected,
+  // so its outline is empty.
+  pub fn silhouette(& self, view: View) -> Option<Silhouette> {
+    if self.is_empty() { return None; }
+    if let View::Orthographic(direction) = view {
+      if !(direction.magnitude2() > 0.0 && direction.magnitude2().is_finite()) { return None; }
+    }
+
+    let front_facing: Vec<bool> = match view {
+      View::Orthographic(direction) => self.planes.iter().map(|plane| plane.normal.dot(direction) < 0.0).collect(),
+      View::Perspective(eye) => self.planes.iter().map(|plane| plane.signed_distance(eye) > 0.0).collect(),
+    };
+    if !front_facing.contains(& true) { return None; }
+
+    // Each silhouette edge is turned to run counterclockwise around its front facing face
+    let mut loop_edges: HashMap<u32, u32> = HashMap::new();
+    let mut edge_index: HashMap<(Idx, Idx), Idx> = HashMap::new();
+    for (e_idx, faces) in self.edge_faces.iter().enumerate() {
+      if front_facing[faces[0]] == front_facing[faces[1]] { continue; }
+      let (from, to) = if front_facing[faces[0]] { (self.edges[e_idx][0], self.edges[e_idx][1]) } else { (self.edges[e_idx][1], self.edges[e_idx][0]) };
+      loop_edges.insert(from as u32, to as u32);
+      edge_index.insert((from, to), e_idx);
+    }
+
+    let chain = chain_edges(loop_edges);
+    let vertices: Vec<Idx> = chain.iter().map(|& (from, _)| from as Idx).collect();
+    let edges: Vec<Idx> = chain.iter().map(|& (from, to)| edge_index[& (from as Idx, to as Idx)]).collect();
+
+    // The axes are right-handed with the direction towards the viewer, so the loop stays counterclockwise
+    let toward_viewer = match view {
+      View::Orthographic(direction) => -direction.normalize(),
+      View::Perspective(eye) => {
+        let middle = self.vertices.iter().fold(Vec3::zero(), |sum, v| sum + v.to_vec()) / self.vertices.len() as f32;
+        let toward_eye = eye - Pt::from_vec(middle);
+        // An eye in the middle is inside the hull and was turned away above, but rounding mustn't make NaNs here
+        if toward_eye.magnitude2() == 0.0 { return None; }
+        toward_eye.normalize()
+      },
+    };
+    let (first, second) = perpendicular_basis(toward_viewer);
+
+    let outline: Vec<[f32; 2]> = match view {
+      View::Orthographic(_) => {
+        vertices.iter().map(|& v| [first.dot(self.vertices[v].to_vec()), second.dot(self.vertices[v].to_vec())]).collect()
+      },
+      View::Perspective(eye) => {
+        if self.vertices.iter().any(|& v| toward_viewer.dot(v - eye) >= 0.0) { Vec::new() } else {
+          vertices.iter()
+            .map(|& v| {
+              let offset = self.vertices[v] - eye;
+              let depth = -toward_viewer.dot(offset);
+              [first.dot(offset) / depth, second.dot(offset) / depth]
+            })
+            .collect()
+        }
+      },
+    };
+
+    Some(Silhouette {
+      front_facing: front_facing,
+      edges: edges,
+      vertices: vertices,
+      axes: [first, second],
+      outline: outline,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use defs::*;
+  use test_support::*;
+  use convex_hull::get_convex_polyhedron;
+  use super::*;
+
+  fn outline_area(outline: & [[f32; 2]]) -> f32 {
+    let mut twice_area = 0.0;
+    for (idx, current) in outline.iter().enumerate() {
+      let next = outline[(idx + 1) % outline.len()];
+      twice_area += current[0] * next[1] - next[0] * current[1];
+    }
+    return twice_area / 2.0;
+  }
+
+  #[test]
+  fn box_along_an_axis() {
+    let hull = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 2.0, 3.0)));
+    let silhouette = hull.silhouette(View::Orthographic(Vec3::new(0.0, 0.0, -1.0))).unwrap();
+    assert_eq!(silhouette.edges.len(), 4);
+    assert_eq!(silhouette.vertices.len(), 4);
+    assert_eq!(silhouette.front_facing.iter().filter(|& & front| front).count(), 1);
+    // The outline is the 2 by 4 side, counterclockwise
+    assert!((outline_area(& silhouette.outline) - 8.0).abs() < 1e-4);
+  }
+
+  #[test]
+  fn box_along_a_diagonal() {
+    let hull = get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+    let silhouette = hull.silhouette(View::Orthographic(Vec3::new(-1.0, -1.0, -1.0))).unwrap();
+    assert_eq!(silhouette.edges.len(), 6);
+    assert_eq!(silhouette.front_facing.iter().filter(|& & front| front).count(), 3);
+    // A regular hexagon, with the corners sqrt(8 / 3) from the middle
+    assert!((outline_area(& silhouette.outline) - 4.0 * 3.0_f32.sqrt()).abs() < 1e-4);
+
+    let from_eye = hull.silhouette(View::Perspective(Pt::new(5.0, 5.0, 5.0))).unwrap();
+    assert_eq!(from_eye.edges.len(), 6);
+    assert!(outline_area(& from_eye.outline) > 0.0);
+  }
+
+  #[test]
+  fn views_which_cannot_see_the_hull() {
+    let hull = get_convex_polyhedron(box_corners(Pt::new(1.0, 2.0, 3.0), Vec3::new(1.0, 1.0, 1.0)));
+    assert!(hull.silhouette(View::Orthographic(Vec3::zero())).is_none());
+    assert!(hull.silhouette(View::Orthographic(Vec3::new(f32::NAN, 0.0, 1.0))).is_none());
+    assert!(hull.silhouette(View::Perspective(Pt::new(1.0, 2.0, 3.0))).is_none());
+  }
+}
@@ -0,0 +1,47 @@
+// Inputs shared by the tests of the different modules
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use cgmath::prelude::*;
+
+use defs::*;
+use polyhedron::ConvexPolyhedron;
+use convex_hull::get_convex_polyhedron;
+
+// Points spread through the unit ball around the origin, the same ones for each seed
+pub fn ball_points(count: usize, seed: u64) -> Vec<Pt> {
+  let mut rng = StdRng::seed_from_u64(seed);
+  let mut points = Vec::with_capacity(count);
+  while points.len() < count {
+    let pt = Vec3::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0));
+    if pt.magnitude2() <= 1.0 { points.push(Pt::from_vec(pt)); }
+  }
+  return points;
+}
+
+// The corners of an axis-aligned box
+pub fn box_corners(center: Pt, half_size: Vec3) -> Vec<Pt> {
+  let mut corners = Vec::with_capacity(8);
+  for & x in [-1.0, 1.0].iter() {
+    for & y in [-1.0, 1.0].iter() {
+      for & z in [-1.0, 1.0].iter() { corners.push(center + Vec3::new(x * half_size.x, y * half_size.y, z * half_size.z)); }
+    }
+  }
+  return corners;
+}
+
+// The hull of the cube from (-1, -1, -1) to (1, 1, 1)
+pub fn cube_hull() -> ConvexPolyhedron {
+  get_convex_polyhedron(box_corners(Pt::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)))
+}
+
+// A cubic grid of points one unit apart, with the given number of points along each side
+pub fn grid_points(side: usize) -> Vec<Pt> {
+  let mut points = Vec::with_capacity(side * side * side);
+  for x in 0..side {
+    for y in 0..side {
+      for z in 0..side { points.push(Pt::new(x as f32, y as f32, z as f32)); }
+    }
+  }
+  return points;
+}